@@ -0,0 +1,121 @@
+// ------------------------------------------------------------------------------------------------
+// mutation.rs
+// ------------------------------------------------------------------------------------------------
+//  Compares scoring the mutation attempts from scratch (cloning the layout and calling 
+//  Layout::score) against applying/undoing them on the occupancy grid.
+//  Run with "cargo bench".
+extern crate polyfarm;
+extern crate rand;
+extern crate time;
+
+use std::cmp;
+use std::env;
+use std::fs::File;
+use std::io::prelude::*;
+use rand::{Rng, SeedableRng, StdRng};
+use time::PreciseTime;
+
+use polyfarm::polyomino::layout::{Layout, Position, parse_bundle};
+use polyfarm::polyomino::occupancy::Occupancy;
+use polyfarm::polyomino::farm::{Farm, FarmConfig};
+
+const LAYOUTS: usize = 200;
+const ATTEMPTS: usize = 500;
+const ROUNDS: usize = 5;
+
+//  picks the random flips of the same kinds mutation::random_flips does, as (chain index, new position)
+fn random_flips<R: Rng>(layout: &Layout, rng: &mut R) -> Vec<(usize, Position)> {
+    let n = layout.pos.len();
+    let mut pos = layout.pos.clone();
+    let mut res = vec![];
+    for _ in 0..rng.gen_range(2, 5) {
+        let (i, j) = (rng.gen_range(0, n), rng.gen_range(0, n));
+        match rng.gen_range(0, 3) {
+            0 => {
+                pos[i].var = rng.gen_range(0, layout.bundle[pos[i].shape as usize].len() as u16);
+                res.push((i, pos[i].clone()));
+            },
+            1 => {
+                let (dx, dy) = (rng.gen_range(-1, 2), rng.gen_range(-1, 2));
+                for (k, p) in pos.iter_mut().enumerate().take(j + 1).skip(i) {
+                    p.x += dx;
+                    p.y += dy;
+                    res.push((k, p.clone()));
+                }
+            },
+            _ => {
+                let (p1, p2) = (pos[i].clone(), pos[j].clone());
+                pos[i] = Position { shape: p2.shape, var: p2.var, ..p1 };
+                pos[j] = Position { shape: p1.shape, var: p1.var, ..p2 };
+                res.push((i, pos[i].clone()));
+                res.push((j, pos[j].clone()));
+            }
+        }
+    }
+    res
+}
+
+//  runs the scoring a few times, returning the best elapsed time and the scores
+fn measure<F>(f: F) -> (i64, Vec<f64>) where F: Fn() -> Vec<f64> {
+    let mut best = i64::MAX;
+    let mut scores = vec![];
+    for _ in 0..ROUNDS {
+        let start = PreciseTime::now();
+        scores = f();
+        best = cmp::min(best, start.to(PreciseTime::now()).num_milliseconds());
+    }
+    (best, scores)
+}
+
+fn main() {
+    let mut contents = String::new();
+    File::open("data/pentomino.txt").unwrap().read_to_string(&mut contents).unwrap();
+    let bundle = parse_bundle(&contents, true, true).unwrap();
+    let seed: &[_] = &[1, 2, 3, 4];
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+    //  freshly arranged layouts (mostly open) and the ones after a short search (mostly closed)
+    let mut layouts: Vec<Layout> = (0..LAYOUTS).map(|_| {
+        let mut layout = Layout::new(&bundle);
+        layout.shuffle(&mut rng);
+        layout.arrange_circle(9.0);
+        layout.center();
+        layout
+    }).collect();
+    let config = FarmConfig {
+        out_file: String::from(env::temp_dir().join("polyfarm_bench.html").to_str().unwrap()),
+        gen_size: 500, max_iter: 10, mut_attempts: 100, ..FarmConfig::default()
+    };
+    let res = Farm::new(&bundle, &config).unwrap().grind().unwrap();
+    layouts.extend(res.best.into_iter().map(|s| s.layout));
+    let flips: Vec<Vec<Vec<(usize, Position)>>> = layouts.iter()
+        .map(|l| (0..ATTEMPTS).map(|_| random_flips(l, &mut rng)).collect()).collect();
+
+    //  the scores have to be the same for every attempt
+    let (open, closed) = layouts.split_at(LAYOUTS);
+    let (open_flips, closed_flips) = flips.split_at(LAYOUTS);
+    for &(name, layouts, flips) in &[("open", open, open_flips), ("closed", closed, closed_flips)] {
+        let (t0, s0) = measure(|| layouts.iter().zip(flips).flat_map(|(layout, attempts)| {
+            attempts.iter().map(move |flips| {
+                let mut cl = layout.clone();
+                for &(i, ref p) in flips {
+                    cl.pos[i] = p.clone();
+                }
+                cl.score()
+            })
+        }).collect());
+        let (t1, s1) = measure(|| layouts.iter().zip(flips).flat_map(|(layout, attempts)| {
+            let mut occ = Occupancy::new(layout);
+            attempts.iter().map(move |flips| {
+                for &(i, ref p) in flips {
+                    occ.set(i, p.clone());
+                }
+                let score = occ.score();
+                occ.undo();
+                score
+            }).collect::<Vec<_>>()
+        }).collect());
+        assert_eq!(s0, s1);
+        println!("{} {} layouts, {} attempts each: clone and score {}ms, occupancy grid {}ms", 
+            layouts.len(), name, ATTEMPTS, t0, t1);
+    }
+}
@@ -0,0 +1,97 @@
+// ------------------------------------------------------------------------------------------------
+// overlap.rs
+// ------------------------------------------------------------------------------------------------
+//  Compares the bit mask overlap/distance checks against the square-by-square ones
+//  over every pair of pentomino variants at every nearby relative position.
+//  Run with "cargo bench".
+extern crate polyfarm;
+extern crate time;
+
+use std::fs::File;
+use std::io::prelude::*;
+use time::PreciseTime;
+
+use polyfarm::polyomino::math::Vec2i;
+use polyfarm::polyomino::shape::{Shape, OFFS};
+use polyfarm::polyomino::layout::{Layout, Overlap, parse_bundle};
+
+const ROUNDS: usize = 20;
+
+//  the square-by-square overlap check
+fn naive_overlap_status(shape1: &Shape, shape2: &Shape, pos1: &Vec2i, pos2: &Vec2i) -> Overlap {
+    let dx = pos1.x - pos2.x;
+    let dy = pos1.y - pos2.y;
+    if shape1.squares.iter().any(|sq| shape2.is_set(sq.x + dx, sq.y + dy)) {
+        return Overlap::Overlap;
+    }
+    let border = shape1.squares.iter().any(|sq| {
+        OFFS.iter().any(|offs| shape2.is_set(sq.x + dx + offs[0], sq.y + dy + offs[1]))
+    });
+    if border { Overlap::Border } else { Overlap::Disjoint }
+}
+
+//  the square-by-square distance
+fn naive_distance(shape1: &Shape, shape2: &Shape, pos1: &Vec2i, pos2: &Vec2i) -> i32 {
+    match naive_overlap_status(shape1, shape2, pos1, pos2) {
+        Overlap::Overlap => -1,
+        Overlap::Border => 0,
+        Overlap::Disjoint => shape1.squares.iter().flat_map(|sq1| {
+            shape2.squares.iter().map(move |sq2| {
+                (pos1.x + sq1.x - pos2.x - sq2.x).abs() + (pos1.y + sq1.y - pos2.y - sq2.y).abs()
+            })
+        }).min().unwrap() - 1
+    }
+}
+
+//  runs the check on every case, returning the elapsed time and the checksum
+fn measure<F>(cases: &[(&Shape, &Shape, Vec2i)], f: F) -> (i64, i64) 
+    where F: Fn(&Shape, &Shape, &Vec2i, &Vec2i) -> i32
+{
+    let origin = Vec2i::new(0, 0);
+    let start = PreciseTime::now();
+    let mut sum = 0i64;
+    for _ in 0..ROUNDS {
+        for &(shape1, shape2, ref pos) in cases {
+            sum += f(shape1, shape2, pos, &origin) as i64;
+        }
+    }
+    (start.to(PreciseTime::now()).num_milliseconds(), sum)
+}
+
+fn main() {
+    let mut contents = String::new();
+    File::open("data/pentomino.txt").unwrap().read_to_string(&mut contents).unwrap();
+    let bundle = parse_bundle(&contents, true, true).unwrap();
+    let shapes: Vec<&Shape> = bundle.iter().flat_map(|v| v.iter()).collect();
+    let mut cases = vec![];
+    for &shape1 in &shapes {
+        for &shape2 in &shapes {
+            for y in -7..8 {
+                for x in -7..8 {
+                    cases.push((shape1, shape2, Vec2i::new(x, y)));
+                }
+            }
+        }
+    }
+    println!("{} shape pairs, {} rounds", cases.len(), ROUNDS);
+
+    //  the results have to be the same for every case
+    let origin = Vec2i::new(0, 0);
+    for &(shape1, shape2, ref pos) in &cases {
+        assert_eq!(naive_overlap_status(shape1, shape2, pos, &origin), 
+            Layout::overlap_status(shape1, shape2, pos, &origin));
+        assert_eq!(naive_distance(shape1, shape2, pos, &origin), 
+            Layout::distance(shape1, shape2, pos, &origin));
+    }
+
+    let to_num = |o: Overlap| match o { Overlap::Overlap => -1, Overlap::Border => 0, _ => 1 };
+    let (t0, s0) = measure(&cases, |s1, s2, p1, p2| to_num(naive_overlap_status(s1, s2, p1, p2)));
+    let (t1, s1) = measure(&cases, |s1, s2, p1, p2| to_num(Layout::overlap_status(s1, s2, p1, p2)));
+    assert_eq!(s0, s1);
+    println!("overlap_status: squares {}ms, bit masks {}ms", t0, t1);
+
+    let (t0, s0) = measure(&cases, naive_distance);
+    let (t1, s1) = measure(&cases, Layout::distance);
+    assert_eq!(s0, s1);
+    println!("distance:       squares {}ms, bit masks {}ms", t0, t1);
+}
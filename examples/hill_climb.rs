@@ -0,0 +1,68 @@
+// ------------------------------------------------------------------------------------------------
+// hill_climb.rs
+// ------------------------------------------------------------------------------------------------
+//  A search strategy defined outside of the crate: a few independent hill climbers,
+//  each taking the best of the mutation attempts if it doesn't score worse.
+//  Run with "cargo run --release --example hill_climb -- [shape file]".
+extern crate polyfarm;
+
+use std::env;
+use std::fs::File;
+use std::io::prelude::*;
+
+use polyfarm::polyomino::layout::{Layout, parse_bundle};
+use polyfarm::polyomino::farm::{Farm, FarmConfig};
+use polyfarm::polyomino::strategy::{Strategy, Context};
+use polyfarm::polyomino::mutation::{mutate, MutationCounts};
+
+const CLIMBERS: usize = 8;
+
+struct HillClimb<'a> {
+    climbers: Vec<(Layout<'a>, f64)>,
+}
+
+impl<'a> Strategy<'a> for HillClimb<'a> {
+    fn initialize(&mut self, ctx: &Context<'a>, seeds: &[Layout<'a>]) {
+        self.climbers = (0..CLIMBERS).map(|k| {
+            let layout = if k < seeds.len() { seeds[k].clone() }
+                         else { ctx.fresh_layout(&mut ctx.rng(0, k + 1)) };
+            let score = layout.score();
+            (layout, score)
+        }).collect();
+    }
+
+    fn step(&mut self, ctx: &Context<'a>, it: u32) {
+        ctx.run_parallel(&mut self.climbers, |_, offset, chunk| {
+            for (i, climber) in chunk.iter_mut().enumerate() {
+                let mut rng = ctx.rng(it, offset + i + 1);
+                let mut layout = mutate(&climber.0, ctx.config.mut_attempts, &ctx.config.mutation,
+                    None, &mut rng, &mut MutationCounts::default());
+                layout.center();
+                let score = layout.score();
+                if score >= climber.1 {
+                    *climber = (layout, score);
+                }
+            }
+        });
+    }
+
+    fn best(&self) -> Vec<(&Layout<'a>, f64)> {
+        let mut res: Vec<_> = self.climbers.iter().map(|c| (&c.0, c.1)).collect();
+        res.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        res
+    }
+}
+
+fn main() {
+    let path = env::args().nth(1).unwrap_or_else(|| String::from("data/pentomino.txt"));
+    let mut contents = String::new();
+    File::open(&path).and_then(|mut f| f.read_to_string(&mut contents))
+        .expect("can't read the shape file");
+    let bundle = parse_bundle(&contents, true, true).unwrap();
+
+    let config = FarmConfig { max_iter: 100, threads: 4, ..FarmConfig::default() };
+    let strategy = Box::new(HillClimb { climbers: vec![] });
+    let mut farm = Farm::with_strategy(&bundle, &config, strategy).unwrap();
+    let res = farm.grind().unwrap();
+    println!("Best score: {}", res.best[0].score);
+}
@@ -0,0 +1,12 @@
+// ------------------------------------------------------------------------------------------------
+// lib.rs
+// ------------------------------------------------------------------------------------------------
+extern crate num;
+extern crate rand;
+extern crate time;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+pub mod polyomino;
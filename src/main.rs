@@ -2,28 +2,317 @@
 // main.rs
 // ------------------------------------------------------------------------------------------------
 extern crate getopts;
-extern crate num;
-extern crate rand;
-extern crate time;
-
-mod polyomino;
+extern crate polyfarm;
 
 use std::env;
+use std::io;
 use std::io::prelude::*;
 use std::fs::File;
-use getopts::Options;
+use std::process;
+use getopts::{Options, Matches};
+
+use polyfarm::polyomino::layout::{Bundle, Layout, parse_bundle};
+use polyfarm::polyomino::farm::{Farm, FarmConfig, Algorithm, Schedule};
+use polyfarm::polyomino::error::PolyfarmError;
+use polyfarm::polyomino::checkpoint::Checkpoint;
+use polyfarm::polyomino::export::{write_json, parse_json, build_layout, read_layouts};
+use polyfarm::polyomino::verify::verify;
+use polyfarm::polyomino::sketch::{parse_sketch, draw_sketch};
+use polyfarm::polyomino::exact::solve_exact;
+use polyfarm::polyomino::enumerate::{enumerate, known_count};
+use polyfarm::polyomino::shape::Transforms;
+use polyfarm::polyomino::grid::Grid;
+use polyfarm::polyomino::shapefile::{ShapeDef, format_shape_file};
+use polyfarm::polyomino::mutation::{MutationConfig, Mutation, MUTATIONS};
 
-use polyomino::layout::{parse_bundle};
-use polyomino::farm::{Farm};
+const DEFAULT_SHAPES_FILE: &str = "data/pentomino.txt";
 
-const DEFAULT_SHAPES_FILE: &'static str = "data/pentomino.txt";
+//  exit code for a layout that doesn't pass the verification
+const EXIT_INVALID: i32 = 2;
 
 fn print_usage(program: &str, opts: Options) {
-    let brief = format!("Usage: {} SHAPES_FILE [options]", program);
+    let brief = format!("Usage: {} SHAPES_FILE [options]\n       \
+        {} verify SHAPES_FILE LAYOUT_FILE [options]\n       \
+        {} exact SHAPES_FILE [options]\n       \
+        {} enumerate SIZE [OUTPUT_FILE] [--kind free|one-sided|fixed] [--grid square|hex]", 
+        program, program, program, program);
     print!("{}", opts.usage(&brief));
 }
 
+//  reads the whole file, mentioning its path in the error
+fn read_file(path: &str) -> Result<String, PolyfarmError> {
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut contents))
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+    Ok(contents)
+}
+
+//  parses a numeric option value, falling back to the default
+fn get_num(matches: &Matches, name: &str, default: u32) -> Result<u32, PolyfarmError> {
+    match matches.opt_str(name) {
+        Some(s) => s.trim_end_matches('%').parse::<u32>().map_err(|_| PolyfarmError::Usage(
+            format!("option '{}{}' expects a non-negative number, got '{}'", 
+                if name.len() == 1 { "-" } else { "--" }, name, s))),
+        None => Ok(default)
+    }
+}
+
+//  parses a floating point option value, falling back to the default
+fn get_float(matches: &Matches, name: &str, default: f64) -> Result<f64, PolyfarmError> {
+    match matches.opt_str(name) {
+        Some(s) => s.parse::<f64>().map_err(|_| PolyfarmError::Usage(
+            format!("option '--{}' expects a number, got '{}'", name, s))),
+        None => Ok(default)
+    }
+}
+
+//  parses an optional numeric option value, falling back to the default
+fn get_opt_num(matches: &Matches, name: &str, default: Option<u32>) 
+    -> Result<Option<u32>, PolyfarmError> 
+{
+    if !matches.opt_present(name) { return Ok(default); }
+    get_num(matches, name, 0).map(Some)
+}
+
+//  parses an optional floating point option value, falling back to the default
+fn get_opt_float(matches: &Matches, name: &str, default: Option<f64>) 
+    -> Result<Option<f64>, PolyfarmError> 
+{
+    if !matches.opt_present(name) { return Ok(default); }
+    get_float(matches, name, 0.0).map(Some)
+}
+
+//  parses an option value that is one of the given names, falling back to the default
+fn get_choice<T: Copy>(matches: &Matches, name: &str, choices: &[(&str, T)], 
+    default: T) -> Result<T, PolyfarmError> 
+{
+    match matches.opt_str(name) {
+        Some(s) => choices.iter().find(|c| c.0 == s).map(|c| c.1).ok_or_else(|| 
+            PolyfarmError::Usage(format!("option '--{}' expects one of {}, got '{}'", name, 
+                choices.iter().map(|c| c.0).collect::<Vec<_>>().join("|"), s))),
+        None => Ok(default)
+    }
+}
+
+//  parses the mutation options: the flips per attempt as "MIN-MAX", 
+//  and the weights as "NAME=WEIGHT,..." (the mutations not mentioned keep theirs)
+fn get_mutation(matches: &Matches, default: MutationConfig) -> Result<MutationConfig, PolyfarmError> {
+    let mut res = default;
+    res.adaptive |= matches.opt_present("adaptive");
+    if let Some(s) = matches.opt_str("flips") {
+        let range: Vec<Option<u32>> = s.split('-').map(|n| n.trim().parse().ok()).collect();
+        match range[..] {
+            [Some(min), Some(max)] => { res.min_flips = min; res.max_flips = max; },
+            [Some(n)] => { res.min_flips = n; res.max_flips = n; },
+            _ => return Err(PolyfarmError::Usage(format!(
+                "option '--flips' expects a number or a range like 2-4, got '{}'", s)))
+        }
+    }
+    if let Some(s) = matches.opt_str("mutation-weights") {
+        for item in s.split(',') {
+            let mut parts = item.splitn(2, '=');
+            let mutation = Mutation::from_name(parts.next().unwrap().trim());
+            let weight = parts.next().and_then(|w| w.trim().parse::<u32>().ok());
+            match (mutation, weight) {
+                (Some(m), Some(w)) => *res.weight_mut(m) = w,
+                _ => return Err(PolyfarmError::Usage(format!(
+                    "option '--mutation-weights' expects NAME=WEIGHT items with the names of {}, got '{}'", 
+                    MUTATIONS.iter().map(|m| m.name()).collect::<Vec<_>>().join("|"), item)))
+            }
+        }
+    }
+    Ok(res)
+}
+
+fn print_config(config: &FarmConfig) {
+    println!("gen_size: {}, seed: {}, max_iter: {}, elites: {}, \
+        mut_percentage: {}, mut_attempts: {}, crossover_rate: {}, cell_side: {}, threads: {}, \
+        output file: {}", 
+        config.gen_size, config.seed, config.max_iter, config.elites, config.mut_percentage, 
+        config.mut_attempts, config.crossover_rate, config.cell_side, config.threads, 
+        config.out_file);   
+    if config.algorithm == Algorithm::Genetic && config.islands > 1 {
+        println!("islands: {}, migration interval: {}, migrants: {}",
+            config.islands, config.migration_interval, config.migrants);
+    }
+    if config.algorithm == Algorithm::Anneal {
+        println!("annealing chains: {}, steps: {}, temperature: {} to {} ({:?}), restarts: {}",
+            config.chains, config.anneal_steps, config.temperature, config.final_temperature,
+            config.schedule, config.restarts);
+    }
+    let mutation = &config.mutation;
+    println!("mutations: {} to {} flips of {}{}", mutation.min_flips, mutation.max_flips,
+        MUTATIONS.iter().filter(|&&m| mutation.weight(m) > 0)
+            .map(|&m| format!("{} ({})", m.name(), mutation.weight(m)))
+            .collect::<Vec<_>>().join(", "),
+        if mutation.adaptive { ", adaptive" } else { "" });
+    let mut limits = vec![];
+    if let Some(limit) = config.time_limit {
+        limits.push(format!("time limit: {}s", limit));
+    }
+    if let Some(n) = config.stall_generations {
+        limits.push(format!("stall generations: {}", n));
+    }
+    if let Some(score) = config.target_score {
+        limits.push(format!("target score: {}", score));
+    }
+    if !limits.is_empty() {
+        println!("{}", limits.join(", "));
+    }
+}
+
+//  reads the layouts from a JSON export or a hand-drawn sketch
+fn read_layout_file<'a>(path: &str, bundle: &'a Bundle) -> Result<Vec<Layout<'a>>, PolyfarmError> {
+    let contents = read_file(path)?;
+    let layouts = if contents.trim_start().starts_with('{') {
+        read_layouts(&contents, bundle)
+    } else {
+        parse_sketch(&contents, bundle).map(|layout| vec![layout])
+    };
+    layouts.map_err(|e| match e {
+        PolyfarmError::InvalidData(msg) => PolyfarmError::InvalidData(format!("{}: {}", path, msg)),
+        e => e
+    })
+}
+
+//  checks the layouts from the JSON file (or a single hand-drawn one) against the bundle,
+//  returns true if all of them are valid farms
+fn run_verify(shapes_file: &str, layout_file: &str, 
+    mirrored: bool, rotated: bool) -> Result<bool, PolyfarmError> 
+{
+    let bundle = parse_bundle(&read_file(shapes_file)?, mirrored, rotated)?;
+    let contents = read_file(layout_file)?;
+    let (layouts, scores) = if contents.trim_start().starts_with('{') {
+        let data = parse_json(&contents)?;
+        (data.solutions.iter().map(|s| build_layout(s, &data.bundle, &bundle)).collect(),
+         data.solutions.iter().map(|s| s.score).collect())
+    } else {
+        (vec![parse_sketch(&contents, &bundle)], vec![None])
+    };
+    
+    let mut all_valid = true;
+    for (i, (layout, score)) in layouts.into_iter().zip(scores).enumerate() {
+        println!("Layout #{}:", i + 1);
+        let layout: Layout = match layout {
+            Ok(layout) => layout,
+            Err(PolyfarmError::InvalidData(msg)) => {
+                println!("  INVALID: {}", msg);
+                all_valid = false;
+                continue;
+            },
+            Err(e) => return Err(e)
+        };
+        let report = verify(&layout);
+        for &(i, j) in &report.overlaps {
+            println!("  overlap: position #{} (shape #{}) and position #{} (shape #{})", 
+                i + 1, layout.pos[i].shape + 1, j + 1, layout.pos[j].shape + 1);
+        }
+        for gap in &report.gaps {
+            println!("  gap of {} square(s) between position #{} (shape #{}) and position #{} (shape #{})", 
+                gap.distance, gap.first + 1, layout.pos[gap.first].shape + 1, 
+                gap.second + 1, layout.pos[gap.second].shape + 1);
+        }
+        match report.area {
+            Some(area) => println!("  enclosed area: {}", area),
+            None => println!("  the wall is not closed")
+        }
+        if let Some(score) = score {
+            if report.area.map(|a| a as f64) != Some(score) && score > 0.0 {
+                println!("  stored score {} doesn't match the enclosed area", score);
+            }
+        }
+        println!("  {}", if report.is_valid() { "VALID" } else { "INVALID" });
+        all_valid &= report.is_valid();
+    }
+    Ok(all_valid)
+}
+
+//  proves the maximum area for the bundle by the exhaustive search,
+//  starting from the best of the known layouts (if any)
+fn run_exact(shapes_file: &str, matches: &Matches, 
+    mirrored: bool, rotated: bool) -> Result<(), PolyfarmError> 
+{
+    let bundle = parse_bundle(&read_file(shapes_file)?, mirrored, rotated)?;
+    if bundle[0][0].grid != Grid::Square {
+        return Err(PolyfarmError::Usage(String::from("exact only works with polyominoes")));
+    }
+    let mut known: Option<Layout> = None;
+    for path in matches.opt_strs("seed-layouts") {
+        for layout in read_layout_file(&path, &bundle)? {
+            if known.as_ref().is_none_or(|k| layout.score() > k.score()) {
+                known = Some(layout);
+            }
+        }
+    }
+    if let Some(ref layout) = known {
+        println!("Starting from the known area of {}", layout.score());
+    }
+    let res = solve_exact(&bundle, known);
+    println!("Search nodes: {}", res.nodes);
+    match res.best {
+        Some(ref best) => {
+            if res.proven {
+                println!("Proven maximum area: {}", best.score);
+            } else {
+                println!("Largest area found: {} (not proven, the wider boxes were skipped)", best.score);
+            }
+            print!("{}", draw_sketch(&best.layout));
+        },
+        None if res.proven => println!("The shapes can't enclose any area"),
+        None => println!("No enclosed area found (not proven, the wider boxes were skipped)")
+    }
+    if let Some(path) = matches.opt_str("j") {
+        write_json(&path, &bundle, res.best.as_slice())?;
+    }
+    Ok(())
+}
+
+//  generates all the polyominoes (or polyhexes) of the given size into a shape file (or prints them),
+//  checking their number against the known one
+fn run_enumerate(size: &str, out_file: Option<&String>, matches: &Matches) 
+    -> Result<(), PolyfarmError> 
+{
+    let size: u32 = size.parse().ok().filter(|&n| n > 0).ok_or_else(|| PolyfarmError::Usage(
+        format!("enumerate expects a positive size, got '{}'", size)))?;
+    let kind = get_choice(matches, "kind", &[("free", Transforms::Free), 
+        ("one-sided", Transforms::OneSided), ("fixed", Transforms::Fixed)], Transforms::Free)?;
+    let grid = get_choice(matches, "grid", &[("square", Grid::Square), ("hex", Grid::Hex)], 
+        Grid::Square)?;
+    let what = if grid == Grid::Hex { "polyhexes" } else { "polyominoes" };
+    let shapes = enumerate(size, kind, grid);
+    if let Some(known) = known_count(size, kind, grid) {
+        if known != shapes.len() as u64 {
+            return Err(PolyfarmError::InvalidData(format!(
+                "generated {} {} {} of size {}, while there are {}", 
+                shapes.len(), kind.name(), what, size, known)));
+        }
+    }
+    let summary = format!("{} {} {} of size {}", shapes.len(), kind.name(), what, size);
+    let defs: Vec<ShapeDef> = shapes.into_iter().map(ShapeDef::new).collect();
+    let text = format!("# {}\n{}", summary, format_shape_file(&defs));
+    match out_file {
+        Some(path) => {
+            File::create(path).and_then(|mut f| f.write_all(text.as_bytes()))
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+            println!("Written {} into {}", summary, path);
+        },
+        None => print!("{}", text)
+    }
+    Ok(())
+}
+
 fn main() {
+    match run() {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn run() -> Result<i32, PolyfarmError> {
     let mut opts = Options::new();
     opts.optflag("h", "help", "print this help text");
     opts.optflag("m", "no-mirror", "don't mirror the shapes");
@@ -37,17 +326,118 @@ fn main() {
     opts.optopt("c", "cell-side", "SVG cell side, pixels", "NUMBER");
     opts.optopt("p", "mut-percentage", "percentage of generation to mutate", "NUMBER%");
     opts.optopt("a", "mut-attempts", "mutation attempts per gene", "NUMBER");
+    opts.optopt("", "crossover-rate", 
+        "percentage of the mutated genes to cross with another one first", "NUMBER%");
+    opts.optopt("", "flips", "random flips per mutation attempt (2-4 by default)", "MIN-MAX");
+    opts.optopt("", "mutation-weights", 
+        "relative weights of the mutations (variant, offset and swap by default)", 
+        "variant|offset|swap|reverse|rotate|refit=NUMBER,...");
+    opts.optflag("", "adaptive", 
+        "adapt the probabilities of the mutations and flip counts to how they do");
+    opts.optopt("t", "threads", "number of worker threads", "NUMBER");
+    opts.optopt("", "islands", 
+        "number of islands to split the generation into (1 by default)", "NUMBER");
+    opts.optopt("", "migration-interval", 
+        "generations between migrations of the best layouts between islands", "NUMBER");
+    opts.optopt("", "migrants", "layouts every island sends to the next one", "NUMBER");
+    opts.optopt("", "algorithm", "search algorithm (genetic by default)", "genetic|anneal");
+    opts.optopt("", "chains", "number of independent annealing chains", "NUMBER");
+    opts.optopt("", "anneal-steps", "annealing steps per chain per iteration", "NUMBER");
+    opts.optopt("", "temperature", "initial annealing temperature", "NUMBER");
+    opts.optopt("", "final-temperature", "annealing temperature at the end of a run", "NUMBER");
+    opts.optopt("", "schedule", "annealing temperature schedule (geometric by default)", 
+        "geometric|linear");
+    opts.optopt("", "restarts", "times to restart the annealing chains from their best layouts", 
+        "NUMBER");
+    opts.optopt("", "time-limit", "stop after running for the given time", "SECONDS");
+    opts.optopt("", "stall-generations", 
+        "stop when the best score hasn't improved for the given number of generations", "NUMBER");
+    opts.optopt("", "target-score", "stop once the enclosed area reaches the score", "NUMBER");
+    opts.optopt("", "config", "read the parameters from a JSON file (the options override them)", 
+        "FILE");
+    opts.optopt("", "checkpoint", "periodically save the search state to the file", "FILE");
+    opts.optopt("", "checkpoint-every", "generations between checkpoints", "NUMBER");
+    opts.optopt("", "resume", "continue the search from a checkpoint file", "FILE");
+    opts.optopt("j", "json", "write the best layouts into a JSON file", "FILE");
+    opts.optopt("", "kind", "polyominoes to enumerate, distinct up to the allowed transforms \
+        (free by default)", "free|one-sided|fixed");
+    opts.optopt("", "grid", "cells of the shapes to enumerate (square by default)", "square|hex");
+    opts.optmulti("", "seed-layouts", 
+        "add the layouts (JSON export or ASCII sketch) to the first generation", "FILE");
+    opts.optopt("", "seed-percentage", 
+        "percentage of the first generation to fill with mutated seed layouts", "NUMBER%");
     
     let args: Vec<String> = env::args().collect();
     let program = args[0].clone();
-    let matches = match opts.parse(&args[1..]) {
-        Ok(m) => { m }
-        Err(f) => { panic!(f.to_string()) }
-    };
+    let matches = opts.parse(&args[1..])
+        .map_err(|f| PolyfarmError::Usage(f.to_string()))?;
 
     if matches.opt_present("h") {
         print_usage(&program, opts);
-        return;
+        return Ok(0);
+    }
+    
+    let mirrored = !matches.opt_present("m");
+    let rotated = !matches.opt_present("r");
+    
+    if matches.free.first().map(|s| s.as_str()) == Some("verify") {
+        if matches.free.len() != 3 {
+            return Err(PolyfarmError::Usage(String::from(
+                "verify expects a shapes file and a layout file")));
+        }
+        let valid = run_verify(&matches.free[1], &matches.free[2], mirrored, rotated)?;
+        return Ok(if valid { 0 } else { EXIT_INVALID });
+    }
+
+    if matches.free.first().map(|s| s.as_str()) == Some("exact") {
+        if matches.free.len() != 2 {
+            return Err(PolyfarmError::Usage(String::from("exact expects a shapes file")));
+        }
+        run_exact(&matches.free[1], &matches, mirrored, rotated)?;
+        return Ok(0);
+    }
+
+    if matches.free.first().map(|s| s.as_str()) == Some("enumerate") {
+        if matches.free.len() < 2 || matches.free.len() > 3 {
+            return Err(PolyfarmError::Usage(String::from(
+                "enumerate expects a size and, optionally, an output file")));
+        }
+        run_enumerate(&matches.free[1], matches.free.get(2), &matches)?;
+        return Ok(0);
+    }
+
+    if let Some(resume_file) = matches.opt_str("resume") {
+        if matches.opt_present("seed-layouts") {
+            return Err(PolyfarmError::Usage(String::from(
+                "seed layouts can't be used when resuming from a checkpoint")));
+        }
+        //  the bundle and the parameters come from the checkpoint
+        let mut checkpoint = Checkpoint::load(&resume_file)?;
+        if let Some(path) = matches.opt_str("checkpoint") {
+            checkpoint.config.checkpoint = Some(path);
+        }
+        if let Some(path) = matches.opt_str("o") {
+            checkpoint.config.out_file = path;
+        }
+        //  allow extending the run
+        checkpoint.config.max_iter = get_num(&matches, "n", checkpoint.config.max_iter)?;
+        checkpoint.config.checkpoint_interval = get_num(&matches, "checkpoint-every", 
+            checkpoint.config.checkpoint_interval)?;
+        //  as well as changing when to stop
+        let config = &mut checkpoint.config;
+        config.time_limit = get_opt_float(&matches, "time-limit", config.time_limit)?;
+        config.stall_generations = get_opt_num(&matches, "stall-generations", 
+            config.stall_generations)?;
+        config.target_score = get_opt_float(&matches, "target-score", config.target_score)?;
+        println!("Resuming from {} at iteration {}", resume_file, checkpoint.iteration);
+        print_config(&checkpoint.config);
+        let bundle = checkpoint.bundle()?;
+        let mut farm = Farm::resume(&bundle, checkpoint)?;
+        let res = farm.grind()?;
+        if let Some(path) = matches.opt_str("j") {
+            write_json(&path, &bundle, &res.best)?;
+        }
+        return Ok(0);
     }
 
     let shapes_file = if !matches.free.is_empty() {
@@ -57,36 +447,58 @@ fn main() {
         String::from(DEFAULT_SHAPES_FILE)
     };
 
-    let mut f = File::open(shapes_file).unwrap();
-    let mut contents = String::new();
-    f.read_to_string(&mut contents).unwrap();
-    
-    let mirrored = !matches.opt_present("m");
-    let rotated = !matches.opt_present("r");
+    let contents = read_file(&shapes_file)?;
         
-    let out_file = matches.opt_str("o")
-        .unwrap_or_else(|| String::from("output.html"));
-    
-    let get_num = |name, default| {
-        matches.opt_str(name)
-        .unwrap_or_else(|| String::from(default))
-        .parse::<u32>().unwrap()
+    let default = match matches.opt_str("config") {
+        Some(path) => FarmConfig::load(&path).map_err(|e| match e {
+            PolyfarmError::Json(e) => PolyfarmError::InvalidData(format!("{}: {}", path, e)),
+            e => e
+        })?,
+        None => FarmConfig::default()
     };
-
-    let seed = get_num("s", "42");
-    let gen_size = get_num("g", "10000");
-    let max_iter = get_num("n", "1000");
-    let cell_side = get_num("c", "10");    
-    let elites = get_num("e", "1");
-    let mut_percentage = get_num("p", "90");
-    let mut_attempts = get_num("a", "1000");
-        
-    println!("gen_size: {}, seed: {}, max_iter: {}, elites: {},\
-        mut_percentage: {}, mut_attempts: {}, cell_side: {}, output file: {}", 
-        gen_size, seed, max_iter, elites, mut_percentage, mut_attempts, cell_side, out_file);   
+    let config = FarmConfig {
+        out_file: matches.opt_str("o").unwrap_or(default.out_file),
+        seed: get_num(&matches, "s", default.seed)?,
+        gen_size: get_num(&matches, "g", default.gen_size)?,
+        max_iter: get_num(&matches, "n", default.max_iter)?,
+        elites: get_num(&matches, "e", default.elites)?,
+        mut_percentage: get_num(&matches, "p", default.mut_percentage)?,
+        mut_attempts: get_num(&matches, "a", default.mut_attempts)?,
+        cell_side: get_num(&matches, "c", default.cell_side)?,
+        threads: get_num(&matches, "t", default.threads)?,
+        checkpoint: matches.opt_str("checkpoint").or(default.checkpoint),
+        checkpoint_interval: get_num(&matches, "checkpoint-every", default.checkpoint_interval)?,
+        seed_percentage: get_num(&matches, "seed-percentage", default.seed_percentage)?,
+        crossover_rate: get_num(&matches, "crossover-rate", default.crossover_rate)?,
+        algorithm: get_choice(&matches, "algorithm", 
+            &[("genetic", Algorithm::Genetic), ("anneal", Algorithm::Anneal)], default.algorithm)?,
+        chains: get_num(&matches, "chains", default.chains)?,
+        anneal_steps: get_num(&matches, "anneal-steps", default.anneal_steps)?,
+        temperature: get_float(&matches, "temperature", default.temperature)?,
+        final_temperature: get_float(&matches, "final-temperature", default.final_temperature)?,
+        schedule: get_choice(&matches, "schedule", 
+            &[("geometric", Schedule::Geometric), ("linear", Schedule::Linear)], default.schedule)?,
+        restarts: get_num(&matches, "restarts", default.restarts)?,
+        islands: get_num(&matches, "islands", default.islands)?,
+        migration_interval: get_num(&matches, "migration-interval", default.migration_interval)?,
+        migrants: get_num(&matches, "migrants", default.migrants)?,
+        time_limit: get_opt_float(&matches, "time-limit", default.time_limit)?,
+        stall_generations: get_opt_num(&matches, "stall-generations", default.stall_generations)?,
+        target_score: get_opt_float(&matches, "target-score", default.target_score)?,
+        mutation: get_mutation(&matches, default.mutation)?,
+    };
+    print_config(&config);
     
-    let bundle = parse_bundle(&contents, mirrored, rotated);
-    let mut farm = Farm::new(&bundle, &out_file, seed, gen_size, max_iter, 
-        elites, mut_percentage, mut_attempts, cell_side);
-    farm.grind();
+    let bundle = parse_bundle(&contents, mirrored, rotated)?;
+    let mut farm = Farm::new(&bundle, &config)?;
+    for path in matches.opt_strs("seed-layouts") {
+        let layouts = read_layout_file(&path, &bundle)?;
+        println!("Seeding with {} layout(s) from {}", layouts.len(), path);
+        farm.add_seeds(&layouts)?;
+    }
+    let res = farm.grind()?;
+    if let Some(path) = matches.opt_str("j") {
+        write_json(&path, &bundle, &res.best)?;
+    }
+    Ok(0)
 }
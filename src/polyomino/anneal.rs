@@ -0,0 +1,178 @@
+// ------------------------------------------------------------------------------------------------
+// anneal.rs
+// ------------------------------------------------------------------------------------------------
+use std::cmp;
+use serde_json;
+use serde_json::Value;
+use rand::{Rng};
+
+use polyomino::layout::{Layout};
+use polyomino::farm::{GenerationStats, Schedule};
+use polyomino::strategy::{Strategy, Context};
+use polyomino::mutation::{random_flips, MutationCounts, MutationRates};
+use polyomino::occupancy::Occupancy;
+use polyomino::error::PolyfarmError;
+
+//  State of an annealing chain
+struct Chain<'a> {
+    layout: Layout<'a>,
+    score: f64,
+    best: Layout<'a>,
+    best_score: f64,
+    mutations: MutationCounts,  //  how the steps of the last iteration did
+}
+
+//  Simulated annealing: every chain keeps changing its layout with random flips,
+//  taking the worse ones with a probability that falls along with the temperature
+#[derive(Default)]
+pub struct Anneal<'a> {
+    chains: Vec<Chain<'a>>,
+    rates: Option<MutationRates>,   //  adapted probabilities of the mutations, if adaptive
+}
+
+impl<'a> Anneal<'a> {
+    //  constructor
+    pub fn new() -> Anneal<'a> {
+        Anneal::default()
+    }
+
+    //  splits the iterations between the annealing runs (the first one and the restarts),
+    //  returning the run the iteration belongs to, its index in the run and the run length
+    fn run(ctx: &Context, it: u32) -> (u32, u32, u32) {
+        let (max_iter, restarts) = (ctx.config.max_iter, ctx.config.restarts);
+        let period = cmp::max(1, max_iter/(restarts + 1));
+        let run = cmp::min((cmp::max(it, 1) - 1)/period, restarts);
+        let len = if run == restarts { cmp::max(period, max_iter - restarts*period) }
+                  else { period };
+        (run, cmp::max(it, 1) - 1 - run*period, len)
+    }
+
+    //  annealing temperature before the given step of the iteration
+    fn temperature(ctx: &Context, it: u32, step: u32) -> f64 {
+        let (_, k, len) = Anneal::run(ctx, it);
+        let steps = ctx.config.anneal_steps as f64;
+        let progress = ((k as f64)*steps + step as f64)/((len as f64)*steps);
+        let (t0, t1) = (ctx.config.temperature, ctx.config.final_temperature);
+        match ctx.config.schedule {
+            Schedule::Geometric => t0*(t1/t0).powf(progress),
+            Schedule::Linear => t0 + (t1 - t0)*progress
+        }
+    }
+
+    //  how the steps of the chains did in the last iteration
+    fn mutations(&self) -> MutationCounts {
+        let mut res = MutationCounts::default();
+        for chain in &self.chains { res.add(&chain.mutations); }
+        res
+    }
+
+    //  runs an iteration of the annealing chain
+    fn anneal_chain<R: Rng>(ctx: &Context, chain: &mut Chain<'a>, it: u32, 
+        rates: Option<&MutationRates>, rng: &mut R) 
+    {
+        let (run, k, _) = Anneal::run(ctx, it);
+        if run > 0 && k == 0 {
+            //  restart from the best layout, heating up again
+            chain.layout = chain.best.clone();
+            chain.score = chain.best_score;
+        }
+        chain.mutations = MutationCounts::default();
+        let mut occ = Occupancy::new(&chain.layout);
+        for step in 0..ctx.config.anneal_steps {
+            let temp = Anneal::temperature(ctx, it, step);
+            let (used, num_flips) = random_flips(&mut occ, &ctx.config.mutation, rates, rng);
+            let score = occ.score();
+            chain.mutations.record(used, num_flips, score > chain.score);
+            if score >= chain.score || rng.gen::<f64>() < ((score - chain.score)/temp).exp() {
+                occ.commit();
+                chain.score = score;
+                if score > chain.best_score {
+                    chain.best_score = score;
+                    chain.best = occ.layout().clone();
+                }
+            } else {
+                occ.undo();
+            }
+        }
+        chain.layout = occ.layout().clone();
+        chain.layout.center();
+    }
+}
+
+impl<'a> Strategy<'a> for Anneal<'a> {
+    //  every chain starts from a known layout (if any) or a fresh one
+    fn initialize(&mut self, ctx: &Context<'a>, seeds: &[Layout<'a>]) {
+        let nchains = ctx.config.chains as usize;
+        self.rates = MutationRates::start(&ctx.config.mutation, None);
+        self.chains = (0..nchains).map(|k| {
+            let layout = if seeds.is_empty() { ctx.fresh_layout(&mut ctx.rng(0, k + 1)) }
+                         else { seeds[k%seeds.len()].clone() };
+            let score = layout.score();
+            Chain { layout: layout.clone(), score, best: layout, best_score: score,
+                mutations: MutationCounts::default() }
+        }).collect();
+    }
+
+    fn step(&mut self, ctx: &Context<'a>, it: u32) {
+        //  every chain has its own random generator, whichever thread it runs on
+        let rates = self.rates.as_ref();
+        ctx.run_parallel(&mut self.chains, |_, offset, chunk| {
+            for (i, chain) in chunk.iter_mut().enumerate() {
+                let mut rng = ctx.rng(it, offset + i + 1);
+                Anneal::anneal_chain(ctx, chain, it, rates, &mut rng);
+            }
+        });
+        let mutations = self.mutations();
+        if let Some(ref mut rates) = self.rates {
+            rates.update(&ctx.config.mutation, &mutations);
+        }
+    }
+
+    fn best(&self) -> Vec<(&Layout<'a>, f64)> {
+        let mut res: Vec<_> = self.chains.iter().map(|c| (&c.best, c.best_score)).collect();
+        res.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        res
+    }
+
+    //  the mean score is the one of the current layouts, rather than of the best ones
+    fn report(&self, ctx: &Context<'a>, it: u32, stats: &mut GenerationStats) -> String {
+        stats.mean_score = self.chains.iter().map(|c| c.score).sum::<f64>()
+            /(self.chains.len() as f64);
+        stats.mutations = self.mutations().stats(&ctx.config.mutation);
+        stats.mutation_rates = self.rates.clone();
+        let temp = if it == 0 { ctx.config.temperature }
+                   else { Anneal::temperature(ctx, it, ctx.config.anneal_steps) };
+        match self.rates {
+            Some(ref rates) => format!(", temperature: {:.3}, {}", temp, 
+                rates.describe(&ctx.config.mutation)),
+            None => format!(", temperature: {:.3}", temp)
+        }
+    }
+
+    //  the current layouts go first, then the best ones
+    fn population(&self) -> Option<Vec<&Layout<'a>>> {
+        Some(self.chains.iter().map(|c| &c.layout)
+            .chain(self.chains.iter().map(|c| &c.best)).collect())
+    }
+
+    //  the adapted rates of the mutations
+    fn state(&self) -> Option<Value> {
+        self.rates.as_ref().and_then(|rates| serde_json::to_value(rates).ok())
+    }
+
+    fn restore(&mut self, ctx: &Context<'a>, mut population: Vec<Layout<'a>>, 
+        state: Option<Value>) -> Result<(), PolyfarmError>
+    {
+        let last: Option<MutationRates> = match state {
+            Some(state) => serde_json::from_value(state)?,
+            None => None
+        };
+        self.rates = MutationRates::start(&ctx.config.mutation, last.as_ref());
+        let best = population.split_off(population.len()/2);
+        self.chains = population.into_iter().zip(best).map(|(layout, best)| {
+            let (score, best_score) = (layout.score(), best.score());
+            Chain { layout, score, best, best_score, mutations: MutationCounts::default() }
+        }).collect();
+        Ok(())
+    }
+}
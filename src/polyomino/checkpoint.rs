@@ -0,0 +1,97 @@
+// ------------------------------------------------------------------------------------------------
+// checkpoint.rs
+// ------------------------------------------------------------------------------------------------
+use std::fs;
+use std::fs::File;
+use std::io::prelude::*;
+use serde_json;
+use serde_json::Value;
+
+use polyomino::layout::{Bundle, Layout, Position};
+use polyomino::export::{VariantData, bundle_data, build_bundle};
+use polyomino::farm::{FarmConfig, GenerationStats};
+use polyomino::error::PolyfarmError;
+
+const CHECKPOINT_VERSION : u32 = 1;
+
+//  Snapshot of a search in progress, enough to continue it bit-exactly.
+//  The random generators are re-derived from (config.seed, iteration)
+//  at every generation, so these two make up the whole RNG state.
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub version: u32,
+    pub config: FarmConfig,
+    pub bundle: Vec<Vec<VariantData>>,      //  every variant of every shape
+    pub iteration: u32,                     //  iteration the population is about to be scored at
+    pub population: Vec<Vec<Position>>,     //  positions of every layout in the generation
+    pub stats: Vec<GenerationStats>,        //  statistics of the generations so far
+    #[serde(default)]
+    pub state: Option<Value>,               //  the search strategy's own data, if any
+}
+
+impl Checkpoint {
+    //  constructor
+    pub fn new(config: &FarmConfig, bundle: &Bundle, iteration: u32, 
+        population: Vec<Vec<Position>>, stats: Vec<GenerationStats>, state: Option<Value>) 
+        -> Checkpoint 
+    {
+        Checkpoint {
+            version: CHECKPOINT_VERSION,
+            config: config.clone(),
+            bundle: bundle_data(bundle),
+            iteration,
+            population,
+            stats,
+            state,
+        }
+    }
+
+    //  rebuilds the bundle the checkpoint was made with
+    pub fn bundle(&self) -> Result<Bundle, PolyfarmError> {
+        build_bundle(&self.bundle)
+    }
+
+    //  checks that the population is consistent with the bundle
+    pub fn validate(&self, bundle: &Bundle) -> Result<(), PolyfarmError> {
+        if self.version != CHECKPOINT_VERSION {
+            return Err(PolyfarmError::InvalidData(format!(
+                "unsupported checkpoint version {}", self.version)));
+        }
+        if self.bundle != bundle_data(bundle) {
+            return Err(PolyfarmError::InvalidData(String::from(
+                "checkpoint was made with a different bundle")));
+        }
+        if self.population.len() != self.config.population_size() {
+            return Err(PolyfarmError::InvalidData(format!(
+                "population size ({}) differs from the expected one ({})",
+                self.population.len(), self.config.population_size())));
+        }
+        for (i, pos) in self.population.iter().enumerate() {
+            Layout::from_positions(bundle, pos.clone()).map_err(|e| match e {
+                PolyfarmError::InvalidData(msg) => 
+                    PolyfarmError::InvalidData(format!("layout #{}: {}", i + 1, msg)),
+                e => e
+            })?;
+        }
+        Ok(())
+    }
+
+    //  writes the checkpoint, replacing the file only once fully written
+    pub fn save(&self, path: &str) -> Result<(), PolyfarmError> {
+        let tmp_path = format!("{}.tmp", path);
+        {
+            let mut file = File::create(&tmp_path)?;
+            serde_json::to_writer(&mut file, self)?;
+            file.flush()?;
+        }
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    //  reads a checkpoint
+    pub fn load(path: &str) -> Result<Checkpoint, PolyfarmError> {
+        let mut contents = String::new();
+        File::open(path)?.read_to_string(&mut contents)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+}
@@ -0,0 +1,121 @@
+// ------------------------------------------------------------------------------------------------
+// enumerate.rs
+// ------------------------------------------------------------------------------------------------
+//  Generates all the polyominoes (or polyhexes) of a given size with Redelmeier's algorithm: the fixed
+//  polyominoes are grown square by square from the first one (the leftmost of the top row),
+//  every square being tried once per branch, so that each of them comes up exactly once.
+//  The one-sided and free ones are then the fixed ones, deduplicated by their canonical forms.
+use std::collections::HashSet;
+
+use polyomino::math::{Vec2i};
+use polyomino::shape::{Shape, Transform, Transforms};
+use polyomino::grid::Grid;
+
+//  known numbers of the polyominoes of 1 to 12 squares (OEIS A000105, A000988, A001168)
+const FREE_COUNTS: [u64; 12] = [1, 1, 2, 5, 12, 35, 108, 369, 1285, 4655, 17073, 63600];
+const ONE_SIDED_COUNTS: [u64; 12] = [1, 1, 2, 7, 18, 60, 196, 704, 2500, 9189, 33896, 126759];
+const FIXED_COUNTS: [u64; 12] = [1, 2, 6, 19, 63, 216, 760, 2725, 9910, 36446, 135268, 505861];
+//  the same for the polyhexes (OEIS A000228, A006535, A001207)
+const HEX_FREE_COUNTS: [u64; 12] = [1, 1, 3, 7, 22, 82, 333, 1448, 6572, 30490, 143552, 683101];
+const HEX_ONE_SIDED_COUNTS: [u64; 12] = 
+    [1, 1, 3, 10, 33, 147, 620, 2821, 13147, 63132, 306265, 1505935];
+const HEX_FIXED_COUNTS: [u64; 12] = 
+    [1, 3, 11, 44, 186, 814, 3652, 16689, 77359, 362671, 1716033, 8171229];
+
+//  the known number of the shapes of the given size, if it's in the table
+pub fn known_count(size: u32, kind: Transforms, grid: Grid) -> Option<u64> {
+    let counts = match (grid, kind) {
+        (Grid::Square, Transforms::Free) => &FREE_COUNTS,
+        (Grid::Square, Transforms::OneSided) => &ONE_SIDED_COUNTS,
+        (Grid::Square, Transforms::Fixed) => &FIXED_COUNTS,
+        (Grid::Hex, Transforms::Free) => &HEX_FREE_COUNTS,
+        (Grid::Hex, Transforms::OneSided) => &HEX_ONE_SIDED_COUNTS,
+        (Grid::Hex, Transforms::Fixed) => &HEX_FIXED_COUNTS,
+    };
+    if size == 0 { None } else { counts.get(size as usize - 1).cloned() }
+}
+
+//  the squares that may be added to a growing polyomino: the ones below the first
+//  square, or to the right of it on the same row
+fn is_allowed(p: Vec2i) -> bool {
+    p.y > 0 || (p.y == 0 && p.x >= 0)
+}
+
+//  adds the untried squares to the polyomino one by one, and grows it further
+//  with the neighbors that weren't seen before on the way
+fn grow(grid: Grid, size: usize, poly: &mut Vec<Vec2i>, mut untried: Vec<Vec2i>, 
+    seen: &mut HashSet<Vec2i>, res: &mut Vec<Vec<Vec2i>>)
+{
+    while let Some(sq) = untried.pop() {
+        poly.push(sq);
+        if poly.len() == size {
+            res.push(poly.clone());
+        } else {
+            let fresh: Vec<Vec2i> = grid.neighbors().iter()
+                .map(|offs| Vec2i::new(sq.x + offs[0], sq.y + offs[1]))
+                .filter(|&p| is_allowed(p) && seen.insert(p)).collect();
+            let mut next = untried.clone();
+            next.extend(&fresh);
+            grow(grid, size, poly, next, seen, res);
+            for p in &fresh { seen.remove(p); }
+        }
+        poly.pop();
+    }
+}
+
+//  generates all the shapes of the given size on the grid, distinct under the given transforms,
+//  each one in its canonical form and carrying the transforms
+pub fn enumerate(size: u32, kind: Transforms, grid: Grid) -> Vec<Shape> {
+    if size == 0 { return vec![]; }
+    let mut fixed = vec![];
+    let origin = Vec2i::new(0, 0);
+    let mut seen: HashSet<Vec2i> = [origin].iter().cloned().collect();
+    grow(grid, size as usize, &mut vec![], vec![origin], &mut seen, &mut fixed);
+
+    //  the squares go to the left of the first one as well
+    let shapes: HashSet<Shape> = fixed.into_iter().map(|squares| {
+        let min_x = squares.iter().map(|p| p.x).min().unwrap();
+        let mut shape = Shape::with_grid(
+            squares.iter().map(|p| Vec2i::new(p.x - min_x, p.y)).collect(), grid);
+        shape.transforms = kind;
+        let mut res = shape.canonical();
+        res.transform = Transform::identity();
+        res
+    }).collect();
+    let mut res: Vec<Shape> = shapes.into_iter().collect();
+    res.sort_by(|a, b| a.squares.cmp(&b.squares));
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enumerate_counts() {
+        for &(grid, max_size) in &[(Grid::Square, 8), (Grid::Hex, 6)] {
+            for size in 1..(max_size + 1) {
+                for &kind in &[Transforms::Free, Transforms::OneSided, Transforms::Fixed] {
+                    let shapes = enumerate(size, kind, grid);
+                    assert_eq!(known_count(size, kind, grid), Some(shapes.len() as u64), 
+                        "{} {} {}", size, kind.name(), grid.name());
+                    assert!(shapes.iter().all(|s| s.squares.len() == size as usize 
+                        && s.transforms == kind && s.grid == grid));
+                }
+            }
+        }
+        assert!(enumerate(0, Transforms::Free, Grid::Square).is_empty());
+        assert_eq!(None, known_count(13, Transforms::Free, Grid::Square));
+    }
+
+    #[test]
+    fn test_enumerate_pentominoes() {
+        //  the same shapes as the hand-typed ones, up to the transforms
+        let data = include_str!("../../data/pentomino.txt");
+        let bundle = ::polyomino::layout::parse_bundle(data, true, true).unwrap();
+        let shapes = enumerate(5, Transforms::Free, Grid::Square);
+        for variants in &bundle {
+            assert_eq!(1, shapes.iter().filter(|s| variants.contains(s)).count());
+        }
+    }
+}
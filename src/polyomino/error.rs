@@ -0,0 +1,77 @@
+// ------------------------------------------------------------------------------------------------
+// error.rs
+// ------------------------------------------------------------------------------------------------
+use std::error::Error;
+use std::fmt;
+use std::io;
+use serde_json;
+
+#[derive(Debug)]
+pub enum PolyfarmError {
+    Io(io::Error),              //  failed to read/write a file
+    Json(serde_json::Error),    //  malformed JSON data
+    Parse {                     //  malformed shape in a shape file
+        shape: usize,           //  shape index (0-based)
+        line: usize,            //  line number in the input (1-based)
+        message: String,
+    },
+    EmptyBundle,                //  no shapes in the input
+    Usage(String),              //  bad command line option
+    Config(String),             //  inconsistent solver parameters
+    InvalidData(String),        //  well-formed, but inconsistent saved data
+}
+
+impl PolyfarmError {
+    //  creates a parse error
+    pub fn parse(shape: usize, line: usize, message: &str) -> PolyfarmError {
+        PolyfarmError::Parse { shape, line, message: String::from(message) }
+    }
+
+    //  relocates a parse error of a standalone shape into a bundle,
+    //  given the shape index and the line the shape starts at
+    pub fn at(self, shape_idx: usize, first_line: usize) -> PolyfarmError {
+        match self {
+            PolyfarmError::Parse { line, message, .. } => PolyfarmError::Parse {
+                shape: shape_idx, line: line + first_line - 1, message
+            },
+            e => e
+        }
+    }
+}
+
+impl fmt::Display for PolyfarmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PolyfarmError::Io(ref e) => write!(f, "I/O error: {}", e),
+            PolyfarmError::Json(ref e) => write!(f, "JSON error: {}", e),
+            PolyfarmError::Parse { shape, line, ref message } => 
+                write!(f, "shape #{} (line {}): {}", shape + 1, line, message),
+            PolyfarmError::EmptyBundle => write!(f, "no shapes found in the input"),
+            PolyfarmError::Usage(ref msg) => write!(f, "{}", msg),
+            PolyfarmError::Config(ref msg) => write!(f, "invalid configuration: {}", msg),
+            PolyfarmError::InvalidData(ref msg) => write!(f, "invalid data: {}", msg),
+        }
+    }
+}
+
+impl Error for PolyfarmError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            PolyfarmError::Io(ref e) => Some(e),
+            PolyfarmError::Json(ref e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for PolyfarmError {
+    fn from(e: io::Error) -> PolyfarmError {
+        PolyfarmError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for PolyfarmError {
+    fn from(e: serde_json::Error) -> PolyfarmError {
+        PolyfarmError::Json(e)
+    }
+}
@@ -0,0 +1,821 @@
+// ------------------------------------------------------------------------------------------------
+// exact.rs
+// ------------------------------------------------------------------------------------------------
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+
+use polyomino::math::{Vec2, Vec2i};
+use polyomino::grid::Grid;
+use polyomino::shape::{Shape};
+use polyomino::layout::{Bundle, Layout, Position};
+use polyomino::farm::{Solution};
+
+//  Outcome of the exhaustive search
+pub struct ExactResult<'a> {
+    pub best: Option<Solution<'a>>,     //  layout with the largest area found, if any encloses
+    pub bound: usize,                   //  upper bound on the area (see wall_bound)
+    pub proven: bool,                   //  whether no larger area is possible (see solve_exact)
+    pub nodes: u64,                     //  number of states the searches went through
+}
+
+//  widest bounding box of the area to search, its rows being 64-bit masks
+//  with a column of margin at each side
+const MAX_WIDTH: usize = 62;
+
+//  "dilates" the row by one cell to both sides
+fn dilate(row: u64) -> u64 {
+    row | (row << 1) | (row >> 1)
+}
+
+//  checks that the area (rows of bit masks) is eight-connected, as the flood fill sees it
+fn is_connected(rows: &[u64]) -> bool {
+    let mut seen = vec![0u64; rows.len()];
+    seen[0] = rows[0] & rows[0].wrapping_neg();
+    loop {
+        let mut changed = false;
+        for y in 0..rows.len() {
+            let mut near = dilate(seen[y]);
+            if y > 0 { near |= dilate(seen[y - 1]); }
+            if y + 1 < rows.len() { near |= dilate(seen[y + 1]); }
+            let grown = seen[y] | (near & rows[y]);
+            if grown != seen[y] {
+                seen[y] = grown;
+                changed = true;
+            }
+        }
+        if !changed { break; }
+    }
+    seen == rows
+}
+
+//  Search for the enclosed areas of the given bounding box, together with the pieces walling
+//  them off. The rows of the area are chosen top to bottom, and as soon as a row of the wall
+//  is known (that is, the area rows above and below it), its cells get covered, left to right.
+//  The area rows are bit masks, bit 0 being the column to the left of the bounding box
+struct Search<'a, 'b> {
+    bundle: &'a Bundle,
+    width: usize,               //  bounding box of the area
+    height: usize,
+    margin: i32,                //  room around the box for the pieces sticking out
+    stride: i32,
+    taken: Vec<bool>,           //  grid cells covered by the pieces
+    row_cells: Vec<usize>,      //  number of covered cells in each grid row
+    rows: Vec<u64>,
+    used: Vec<bool>,
+    pos: Vec<Position>,         //  in the grid coordinates
+    area: usize,
+    wall: usize,                //  wall cells in the rows done
+    waste: usize,               //  covered cells in the rows done, which are not in the wall
+    squares: usize,             //  covered cells
+    total: usize,               //  squares in the bundle
+    min_wall: usize,            //  smallest wall around an area of the bounding box
+    target: &'b mut usize,      //  the area to reach
+    nodes: &'b mut u64,
+    found: Option<(Layout<'a>, usize)>,     //  certified layout, with the area it encloses
+}
+
+impl<'a, 'b> Search<'a, 'b> {
+    fn idx(&self, x: i32, y: i32) -> usize {
+        (x + self.margin + (y + self.margin)*self.stride) as usize
+    }
+
+    //  area row, or an empty one outside of the rows chosen so far
+    fn row(&self, y: i32) -> u64 {
+        if y < 0 || y >= self.rows.len() as i32 { 0 } else { self.rows[y as usize] }
+    }
+
+    //  wall cells of the grid row, which is only final once the area row below it is chosen
+    fn wall_mask(&self, y: i32) -> u64 {
+        dilate(self.row(y - 1) | self.row(y) | self.row(y + 1)) & !self.row(y)
+    }
+
+    //  the grid cells that may end up in the wall: the bounding box and one cell around it
+    fn in_frame(&self, x: i32, y: i32) -> bool {
+        x >= -1 && x <= self.width as i32 && y >= -1 && y <= self.height as i32
+    }
+
+    fn is_core(&self, x: i32, y: i32) -> bool {
+        x >= 0 && x < self.width as i32 && self.row(y) & (2 << x) != 0
+    }
+
+    //  the cells of the area
+    fn core(&self) -> Vec<Vec2i> {
+        let (w, h) = (self.width as i32, self.rows.len() as i32);
+        (0..h).flat_map(|y| (0..w).map(move |x| Vec2i::new(x, y)))
+            .filter(|c| self.is_core(c.x, c.y)).collect()
+    }
+
+    //  area cells still available in the area row (not covered by the pieces reaching below)
+    fn free_mask(&self, y: i32) -> u64 {
+        (0..self.width as i32).filter(|&x| !self.taken[self.idx(x, y)])
+            .fold(0, |acc, x| acc | (2 << x))
+    }
+
+    //  the fewest wall cells in the grid rows below the given one, once the area row under it
+    //  is chosen: the cells under the columns going on below, and above and under the columns
+    //  not reached yet, then for each area row left one more cell on each side and the two
+    //  corners under the last one (see solve_exact). The row under the area is known exactly
+    fn rest_wall(&self, y: i32) -> usize {
+        let h = self.height as i32;
+        if y >= h { return 0; }
+        if y == h - 1 { return dilate(self.rows[y as usize]).count_ones() as usize; }
+        let reached = self.rows.iter().fold(0, |acc, r| acc | r);
+        let full = ((1u64 << self.width) - 1) << 1;
+        let open = (self.row(y + 1) | (full & !reached)).count_ones() as usize;
+        let untouched = (full & !reached).count_ones() as usize;
+        open + untouched + 2*(h - 1 - y) as usize + 2
+    }
+
+    //  covered cells in the grid rows below the given one
+    fn cells_below(&self, y: i32) -> usize {
+        let first = cmp::max(0, y + 1 + self.margin) as usize;
+        self.row_cells[cmp::min(first, self.row_cells.len())..].iter().sum()
+    }
+
+    //  checks that the squares left can still close the wall, and that the area can grow
+    //  to the target
+    fn can_close(&self, y: i32) -> bool {
+        let rest = self.rest_wall(y);
+        let wall = cmp::max(self.min_wall, self.wall + rest);
+        self.waste + wall <= self.total &&
+            self.squares + rest.saturating_sub(self.cells_below(y)) <= self.total
+    }
+
+    //  area rows that can go in the row k, with the fewest wall cells each of them leads to
+    //  (as rest_wall, along with the wall row above it, final with it, and the cells beside
+    //  it in its own row). The cells a column adds only depend on it and its neighbours,
+    //  so the columns get chosen left to right, and a choice is only followed when
+    //  the cheapest way to go on from it (worked out from the right beforehand) fits the budget
+    fn row_masks(&self, k: usize, budget: usize, least_area: usize) -> Vec<(u64, usize)> {
+        let w = self.width as i32;
+        let last = k + 1 == self.height;
+        let (above, prev) = (self.row(k as i32 - 2), self.row(k as i32 - 1));
+        let near = dilate(above | prev);
+        let near_prev = dilate(prev);
+        let reached = self.rows.iter().fold(0, |acc, r| acc | r);
+        let free = self.free_mask(k as i32);
+        let bit = |m: u64, x: i32| m & (1 << (x + 1)) != 0;
+        let cost = |x: i32, l: bool, m: bool, r: bool| {
+            let above = !bit(prev, x) && (bit(near, x) || l || m || r);
+            let beside = !m && (bit(near_prev, x) || l || r);
+            let below = if last { (l || m || r) as usize }
+                else if x < 0 || x >= w { 0 }
+                else if m { 1 }
+                else if !bit(reached, x) { 2 }
+                else { 0 };
+            above as usize + beside as usize + below
+        };
+        let fixed = if last { 0 } else { 2*(self.height - 1 - k) + 2 };
+        if fixed > budget { return vec![]; }
+        //  the columns not reached yet have to be in the last row
+        let choices = |x: i32| match (bit(free, x), last && !bit(reached, x)) {
+            (true, true) => vec![true],
+            (true, false) => vec![true, false],
+            (false, true) => vec![],
+            (false, false) => vec![false],
+        };
+
+        //  cheapest[x][l][m]: the fewest cells the columns from x on add,
+        //  with the columns x - 1 and x chosen
+        const NONE: usize = usize::MAX/2;
+        let mut cheapest = vec![[[NONE; 2]; 2]; w as usize];
+        for x in (0..w).rev() {
+            for l in 0..2 {
+                for &m in &choices(x) {
+                    cheapest[x as usize][l][m as usize] = if x == w - 1 {
+                        cost(x, l == 1, m, false) + cost(x + 1, m, false, false)
+                    } else {
+                        choices(x + 1).iter().map(|&r| cost(x, l == 1, m, r) +
+                            cheapest[x as usize + 1][m as usize][r as usize]).min().unwrap_or(NONE)
+                    };
+                }
+            }
+        }
+
+        let mut res = vec![];
+        let mut stack = vec![(0, false, false, 0u64, 0)];
+        while let Some((x, ll, l, mask, cells)) = stack.pop() {
+            for &m in choices(x).iter().rev() {
+                let mask = if m { mask | (2 << x) } else { mask };
+                if mask.count_ones() as usize + ((w - 1 - x) as usize) < least_area { continue; }
+                let cells = cells + cost(x - 1, ll, l, m);
+                let least = cells + cheapest[x as usize][l as usize][m as usize] + fixed;
+                if least > budget { continue; }
+                if x < w - 1 {
+                    stack.push((x + 1, l, m, mask, cells));
+                } else if mask != 0 {
+                    res.push((mask, least));
+                }
+            }
+        }
+        res
+    }
+
+    //  chooses the area row k
+    fn choose_row(&mut self, k: usize) {
+        if k == self.height {
+            self.start_row(k as i32 - 1);
+            return;
+        }
+        if self.waste + self.min_wall > self.total { return; }
+        let free: Vec<u64> = ((k + 1)..self.height).map(|y| self.free_mask(y as i32)).collect();
+        let full = ((1u64 << self.width) - 1) << 1;
+        let reached = self.rows.iter().fold(0, |acc, r| acc | r);
+        let budget = self.total - self.waste - self.wall;
+        let below: usize = free.iter().map(|m| m.count_ones() as usize).sum();
+        let least_area = (*self.target).saturating_sub(self.area + below);
+        for (mask, cells) in self.row_masks(k, budget, least_area) {
+            let area = self.area + mask.count_ones() as usize;
+            //  a column left by the area costs two more wall cells to come back to
+            let back = (budget - cmp::max(cells, self.min_wall.saturating_sub(self.wall)))/2;
+            let open = mask | (full & !(reached | mask));
+            let room: usize = free.iter().map(|m| cmp::min(m.count_ones() as usize,
+                (m & open).count_ones() as usize + back)).sum();
+            if area + room >= *self.target {
+                self.rows.push(mask);
+                self.area = area;
+                self.start_row(k as i32 - 1);
+                self.area -= mask.count_ones() as usize;
+                self.rows.pop();
+                if self.found.is_some() { return; }
+            }
+        }
+    }
+
+    //  starts covering the wall in the grid row
+    fn start_row(&mut self, y: i32) {
+        *self.nodes += 1;
+        let wall = self.wall_mask(y);
+        let waste = (-1..(self.width as i32 + 1))
+            .filter(|&x| self.taken[self.idx(x, y)] && wall & (1 << (x + 1)) == 0)
+            .count();
+        self.wall += wall.count_ones() as usize;
+        self.waste += waste;
+        if self.can_close(y) {
+            self.cover(y, wall);
+        }
+        self.wall -= wall.count_ones() as usize;
+        self.waste -= waste;
+    }
+
+    //  goes on once the grid row is walled: chooses the next area row,
+    //  or checks the area when the wall is complete
+    fn next_row(&mut self, y: i32) {
+        let h = self.height as i32;
+        if y < h - 1 {
+            self.choose_row((y + 2) as usize);
+        } else if y == h - 1 {
+            self.start_row(h);
+        } else if is_connected(&self.rows) {
+            let core = self.core();
+            self.found = certify(self.bundle, &core, &self.pos).map(|layout| (layout, core.len()));
+        }
+    }
+
+    //  covers the first wall cell of the row with each of the pieces fitting there
+    fn cover(&mut self, y: i32, wall: u64) {
+        let x = match (-1..(self.width as i32 + 1))
+            .find(|&x| wall & (1 << (x + 1)) != 0 && !self.taken[self.idx(x, y)])
+        {
+            Some(x) => x,
+            None => return self.next_row(y),
+        };
+        let bundle = self.bundle;
+        for (i, variants) in bundle.iter().enumerate() {
+            if self.used[i] { continue; }
+            for (var, shape) in variants.iter().enumerate() {
+                for sq in &shape.squares {
+                    let p = Vec2i::new(x - sq.x, y - sq.y);
+                    if !self.fits(shape, p) { continue; }
+                    let waste = self.place(shape, p, y, wall, true);
+                    self.used[i] = true;
+                    self.pos.push(Position { x: p.x, y: p.y, shape: i as u16, var: var as u16 });
+                    if self.can_close(y) {
+                        self.cover(y, wall);
+                    }
+                    self.pos.pop();
+                    self.used[i] = false;
+                    self.place(shape, p, y, wall, false);
+                    self.waste -= waste;
+                    if self.found.is_some() { return; }
+                }
+            }
+        }
+    }
+
+    //  the piece has to stay off the other pieces and the area
+    fn fits(&self, shape: &Shape, p: Vec2i) -> bool {
+        shape.squares.iter().all(|s| {
+            let (x, y) = (p.x + s.x, p.y + s.y);
+            !self.taken[self.idx(x, y)] && !self.is_core(x, y)
+        })
+    }
+
+    //  puts the piece on the grid (or takes it off), returns its cells that are already
+    //  known to be out of the wall (off the frame, in the rows done, or off the wall
+    //  in the current row). Only the cells in the frame count in row_cells
+    fn place(&mut self, shape: &Shape, p: Vec2i, y: i32, wall: u64, on: bool) -> usize {
+        let mut waste = 0;
+        for s in &shape.squares {
+            let (cx, cy) = (p.x + s.x, p.y + s.y);
+            let idx = self.idx(cx, cy);
+            self.taken[idx] = on;
+            if !self.in_frame(cx, cy) {
+                waste += 1;
+                continue;
+            }
+            let row = (cy + self.margin) as usize;
+            if on { self.row_cells[row] += 1; } else { self.row_cells[row] -= 1; }
+            if cy < y || (cy == y && wall & (1 << (cx + 1)) == 0) {
+                waste += 1;
+            }
+        }
+        if on {
+            self.squares += shape.squares.len();
+            self.waste += waste;
+        } else {
+            self.squares -= shape.squares.len();
+        }
+        waste
+    }
+}
+
+//  the cell the flood fill of the layout starts from (unless it is covered)
+fn fill_start(layout: &Layout) -> Vec2i {
+    let (lt, rb) = layout.bounds();
+    Vec2i::new(lt.x + (rb.x - lt.x + 1)/2, lt.y + (rb.y - lt.y + 1)/2)
+}
+
+//  places the pieces left out of the wall around it, each next to a piece already placed,
+//  trying the spots one after another until the flood fill of the layout starts in the area
+fn place_spare(layout: &mut Layout, taken: &mut HashSet<Vec2i>, core: &HashSet<Vec2i>,
+    spare: &[usize]) -> bool
+{
+    let i = match spare.first() {
+        Some(&i) => i,
+        None => return core.contains(&fill_start(layout)),
+    };
+    let bundle = layout.bundle;
+    let is_free = |c: Vec2i| !taken.contains(&c) && !core.contains(&c);
+    let mut spots = vec![];
+    for (var, shape) in bundle[i].iter().enumerate() {
+        for anchor in &layout.pos {
+            let anchor_shape = &bundle[anchor.shape as usize][anchor.var as usize];
+            for b in &anchor_shape.boundary {
+                for s in &shape.squares {
+                    let p = *b + anchor.p() - *s;
+                    if shape.squares.iter().all(|&s| is_free(s + p)) && !spots.contains(&(var, p)) {
+                        spots.push((var, p));
+                    }
+                }
+            }
+        }
+    }
+    for (var, p) in spots {
+        let squares: Vec<Vec2i> = bundle[i][var].squares.iter().map(|&s| s + p).collect();
+        taken.extend(squares.iter().cloned());
+        layout.pos.push(Position { x: p.x, y: p.y, shape: i as u16, var: var as u16 });
+        if place_spare(layout, taken, core, &spare[1..]) { return true; }
+        layout.pos.pop();
+        for s in &squares { taken.remove(s); }
+    }
+    false
+}
+
+//  turns the wall found by the search into a layout enclosing exactly the given area, checking
+//  it directly rather than trusting the search: the pieces stay off each other and the area,
+//  every cell around the area (as the flood fill goes, eight-connected) is covered, and the area
+//  is connected. The pieces left out of the wall then go around it, so that the flood fill starts
+//  in the area, and so finds all of its cells and nothing else
+fn certify<'a>(bundle: &'a Bundle, core: &[Vec2i], pos: &[Position]) -> Option<Layout<'a>> {
+    let core: HashSet<Vec2i> = core.iter().cloned().collect();
+    let mut taken = HashSet::new();
+    for p in pos {
+        for &s in &bundle[p.shape as usize][p.var as usize].squares {
+            if !taken.insert(s + p.p()) || core.contains(&(s + p.p())) { return None; }
+        }
+    }
+    let offsets = Grid::Square.fill_neighbors();
+    let around = |c: Vec2i| offsets.iter().map(move |o| Vec2i::new(c.x + o[0], c.y + o[1]));
+    if !core.iter().all(|&c| around(c).all(|n| core.contains(&n) || taken.contains(&n))) {
+        return None;
+    }
+    let mut reached: HashSet<Vec2i> = core.iter().take(1).cloned().collect();
+    let mut queue: Vec<Vec2i> = reached.iter().cloned().collect();
+    while let Some(c) = queue.pop() {
+        for n in around(c) {
+            if core.contains(&n) && reached.insert(n) { queue.push(n); }
+        }
+    }
+    if core.is_empty() || reached.len() != core.len() { return None; }
+
+    let spare: Vec<usize> = (0..bundle.len())
+        .filter(|&i| !pos.iter().any(|p| p.shape as usize == i)).collect();
+    let mut layout = Layout { bundle, pos: pos.to_vec() };
+    if !place_spare(&mut layout, &mut taken, &core, &spare) { return None; }
+    layout.sort_by_angle();
+    Some(layout)
+}
+
+//  checks whether each piece has a variant for every image of its variants under the mapping
+fn has_images<F>(bundle: &Bundle, map: F) -> bool
+    where F: Fn(&Vec2i) -> Vec2i
+{
+    let sorted = |squares: &[Vec2i]| {
+        let mut res = squares.to_vec();
+        res.sort();
+        res
+    };
+    bundle.iter().all(|variants| variants.iter().all(|shape| {
+        let image: Vec<Vec2i> = shape.squares.iter().map(&map).collect();
+        let min_x = image.iter().map(|s| s.x).min().unwrap_or(0);
+        let min_y = image.iter().map(|s| s.y).min().unwrap_or(0);
+        let image = sorted(&image.iter().map(|s| Vec2i::new(s.x - min_x, s.y - min_y)).collect::<Vec<_>>());
+        variants.iter().any(|v| sorted(&v.squares) == image)
+    }))
+}
+
+//  checks whether the bundle can be transposed
+//  (then only the boxes not wider than high need searching)
+fn is_transposable(bundle: &Bundle) -> bool {
+    has_images(bundle, |s| Vec2i::new(s.y, s.x))
+}
+
+//  checks whether each piece has a variant for every rotated one
+//  (then the wall loop can always be turned to start heading east)
+fn is_rotatable(bundle: &Bundle) -> bool {
+    has_images(bundle, |s| Vec2i::new(-s.y, s.x))
+}
+
+//  directions along the wall loop, clockwise (y goes down)
+const DIRS: [Vec2i; 4] = [
+    Vec2 { x: 1, y: 0 }, Vec2 { x: 0, y: 1 }, Vec2 { x: -1, y: 0 }, Vec2 { x: 0, y: -1 }];
+
+fn cross(a: Vec2i, b: Vec2i) -> i64 {
+    (a.x*b.y - a.y*b.x) as i64
+}
+
+//  Stretch of the wall loop going through a piece: a path through the cells of one of its variants,
+//  entering the first cell in the given direction and leaving the last one in the exit direction
+struct Run {
+    len: usize,             //  cells on the path
+    last: Vec2i,            //  the last cell, relative to the first one
+    exit: usize,
+    cross: i64,             //  sum of the cross products of the cells along the path (and the one
+                            //  after it), relative to the first cell
+    inside: usize,          //  cells of the piece off the path, on the inner (right) side of it
+}
+
+//  lists the runs of every piece, by the direction they enter in and the piece. The cells of the piece off
+//  the path go to the side of the path they touch, and the run is dropped if they touch both
+//  sides (the loop would have to cross the piece), as well as when it turns back,
+//  or the cells before and after it are in the piece
+fn list_runs(bundle: &Bundle) -> Vec<Vec<Vec<Run>>> {
+    let adjacent = |a: Vec2i, b: Vec2i| (a.x - b.x).abs() + (a.y - b.y).abs() == 1;
+    let dir_of = |a: Vec2i, b: Vec2i| DIRS.iter().position(|&d| a + d == b).unwrap();
+    let mut res: Vec<Vec<Vec<Run>>> = (0..4)
+        .map(|_| bundle.iter().map(|_| vec![]).collect()).collect();
+    for (i, variants) in bundle.iter().enumerate() {
+        for shape in variants {
+            let cells = &shape.squares;
+            let n = cells.len();
+            let mut paths = vec![];
+            let mut stack: Vec<Vec<usize>> = (0..n).map(|k| vec![k]).collect();
+            while let Some(path) = stack.pop() {
+                let last = cells[*path.last().unwrap()];
+                for (k, &cell) in cells.iter().enumerate() {
+                    if !path.contains(&k) && adjacent(last, cell) {
+                        let mut longer = path.clone();
+                        longer.push(k);
+                        stack.push(longer);
+                    }
+                }
+                paths.push(path);
+            }
+            for path in &paths {
+                let first = cells[path[0]];
+                let on: Vec<Vec2i> = path.iter().map(|&k| cells[k] - first).collect();
+                let off: Vec<Vec2i> = (0..n).filter(|k| !path.contains(k))
+                    .map(|k| cells[k] - first).collect();
+                let m = on.len();
+                for d in 0..4 {
+                    for (e, &step) in DIRS.iter().enumerate() {
+                        let ins: Vec<usize> = (0..m)
+                            .map(|k| if k == 0 { d } else { dir_of(on[k - 1], on[k]) }).collect();
+                        let outs: Vec<usize> = (0..m)
+                            .map(|k| if k + 1 == m { e } else { dir_of(on[k], on[k + 1]) }).collect();
+                        if (0..m).any(|k| outs[k] == (ins[k] + 2) % 4) { continue; }
+                        let before = Vec2i::new(0, 0) - DIRS[d];
+                        let after = on[m - 1] + step;
+                        if cells.iter().any(|&c| c - first == before || c - first == after) { continue; }
+
+                        //  side of each cell off the path: 1 inside (on the right), 2 outside
+                        let mut side = vec![0u8; off.len()];
+                        for k in 0..m {
+                            let (a, b) = (ins[k], outs[k]);
+                            for (v, &dir) in DIRS.iter().enumerate() {
+                                if v == (a + 2) % 4 || v == b { continue; }
+                                if let Some(j) = off.iter().position(|&c| c == on[k] + dir) {
+                                    //  going straight, only the right neighbour is inside; turning,
+                                    //  the neighbours left are all on the outer side of a right turn
+                                    //  and on the inner side of a left one
+                                    let inside = if b == a { v == (a + 1) % 4 } else { b == (a + 3) % 4 };
+                                    side[j] |= if inside { 1 } else { 2 };
+                                }
+                            }
+                        }
+                        loop {
+                            let mut changed = false;
+                            for j in 0..off.len() {
+                                for l in 0..off.len() {
+                                    if adjacent(off[j], off[l]) && side[j] | side[l] != side[j] {
+                                        side[j] |= side[l];
+                                        changed = true;
+                                    }
+                                }
+                            }
+                            if !changed { break; }
+                        }
+                        if side.iter().any(|&s| s != 1 && s != 2) { continue; }
+
+                        let cross = (0..(m - 1)).map(|k| cross(on[k], on[k + 1])).sum::<i64>() +
+                            cross(on[m - 1], after);
+                        res[d][i].push(Run {
+                            len: m, last: on[m - 1], exit: e, cross,
+                            inside: side.iter().filter(|&&s| s == 1).count(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    res
+}
+
+//  counts the empty cells of the shape's box, which are cut off from the outside
+//  (going from each cell to one of its four neighbours)
+fn count_holes(shape: &Shape) -> usize {
+    let (w, h) = (shape.width, shape.height);
+    let mut outside: HashSet<Vec2i> = HashSet::new();
+    let mut queue: Vec<Vec2i> = (-1..(w + 1))
+        .flat_map(|x| vec![Vec2i::new(x, -1), Vec2i::new(x, h)])
+        .chain((0..h).flat_map(|y| vec![Vec2i::new(-1, y), Vec2i::new(w, y)])).collect();
+    outside.extend(queue.iter().cloned());
+    while let Some(c) = queue.pop() {
+        for &d in &DIRS {
+            let n = c + d;
+            if n.x >= -1 && n.x <= w && n.y >= -1 && n.y <= h && !shape.squares.contains(&n) &&
+                outside.insert(n)
+            {
+                queue.push(n);
+            }
+        }
+    }
+    ((w + 2)*(h + 2)) as usize - outside.len() - shape.squares.len()
+}
+
+//  finds an upper bound on the area the bundle can enclose, which is at least the known one.
+//  Any enclosed area lies inside a simple loop of covered cells going from each cell to one
+//  of its four neighbours, and the loop can be made to go through each piece in one stretch
+//  (joining any two stretches through the piece, the area stays on one side of the joint).
+//  Going clockwise, with L cells and the doubled polygon area S (the sum of the cross products
+//  of the cells along it), the loop has (S - L)/2 + 1 cells inside (Pick's theorem), less those
+//  of its pieces on the inner side of it. Such loops are searched piece by piece, starting
+//  at the first cell of the lowest piece, entering it at (0, 0) eastwards (or each way,
+//  unless the pieces can be rotated, which turns the loop so). The states are
+//  the cell the loop goes on to, its direction and the pieces used, keeping only the best
+//  value of S - L - 2*inside for each; a state is dropped when the loop can't come back
+//  with the pieces left, or can't beat the best area found even by enclosing the most
+//  a closed rectilinear curve of its length can (the length squared over 16).
+//  The known layout has such a loop too, so only the loops beating it are of interest.
+//  A loop through one piece only (which the runs leave out) encloses no more than
+//  the holes of the piece, so the bound starts from the largest of them.
+//  The number of states visited is added to the nodes (a state is packed into 64 bits,
+//  so with too many pieces there is no bound)
+pub fn wall_bound(bundle: &Bundle, known: usize, nodes: &mut u64) -> usize {
+    let n = bundle.len();
+    let runs = list_runs(bundle);
+    let longest: Vec<i64> = (0..n)
+        .map(|i| runs.iter().flat_map(|r| r[i].iter()).map(|run| run.len as i64).max().unwrap_or(0))
+        .collect();
+    //  the pieces used in the lowest bits, then the direction and the cell (from -reach to reach)
+    let reach: i64 = longest.iter().sum();
+    let bits = 64 - (2*reach as u64).leading_zeros() as usize;
+    if n + 2 + 2*bits > 64 { return usize::MAX; }
+    let key = |q: Vec2i, d: usize, used: u64| used | (d as u64) << n |
+        ((q.x as i64 + reach) as u64) << (n + 2) | ((q.y as i64 + reach) as u64) << (n + 2 + bits);
+    let mask = |k: u64, shift: usize, bits: usize| (k >> shift) & ((1 << bits) - 1);
+    let starts: Vec<usize> = if is_rotatable(bundle) { vec![0] } else { (0..4).collect() };
+    let holes = bundle.iter().flat_map(|v| v.iter()).map(count_holes).max().unwrap_or(0);
+    let mut best = 2*cmp::max(known, holes) as i64 - 2;
+    for first in 0..n {
+        for &start in &starts {
+            let mut states: HashMap<u64, i64> = HashMap::new();
+            states.insert(key(Vec2i::new(0, 0), start, 0), 0);
+            while !states.is_empty() {
+                let mut next = HashMap::new();
+                for (&k, &value) in &states {
+                    *nodes += 1;
+                    let used = mask(k, 0, n);
+                    let d = mask(k, n, 2) as usize;
+                    let p = Vec2i::new((mask(k, n + 2, bits) as i64 - reach) as i32,
+                        (mask(k, n + 2 + bits, bits) as i64 - reach) as i32);
+                    //  the pieces after the first one, which are left
+                    let left: i64 = ((first + 1)..n).filter(|&i| used & (1 << i) == 0)
+                        .map(|i| longest[i]).sum();
+                    let pieces = if used == 0 { first..(first + 1) } else { (first + 1)..n };
+                    for i in pieces.filter(|&i| used & (1 << i) == 0) {
+                        let left = if used == 0 { left } else { left - longest[i] };
+                        let used = used | (1 << i);
+                        for run in &runs[d][i] {
+                            let step = run.last + DIRS[run.exit];
+                            let q = p + step;
+                            let value = value + cross(p, step) + run.cross -
+                                run.len as i64 - 2*run.inside as i64;
+                            if q == Vec2i::new(0, 0) && run.exit == start {
+                                best = cmp::max(best, value);
+                                continue;
+                            }
+                            let (dx, dy) = (q.x.abs() as i64, q.y.abs() as i64);
+                            if dx + dy > left { continue; }
+                            //  the rest of the loop of length l, closed by a straight line back
+                            //  to (0, 0), adds at most (l + dx + dy)^2/8 + dx*dy (doubled) and
+                            //  takes away l; that is largest at either end of the lengths possible
+                            let most = |l: i64| ((l + dx + dy)*(l + dx + dy) + 7)/8 + dx*dy - l;
+                            if value + cmp::max(most(dx + dy), most(left)) <= best { continue; }
+                            let e = next.entry(key(q, run.exit, used)).or_insert(i64::MIN);
+                            *e = cmp::max(*e, value);
+                        }
+                    }
+                }
+                states = next;
+            }
+        }
+    }
+    ((best + 2)/2) as usize
+}
+
+//  lists the bounding boxes an area walled off by the given number of squares can have,
+//  the largest first (if the bundle can be transposed, only those not wider than high)
+fn list_boxes(total: usize, transposable: bool) -> Vec<(usize, usize)> {
+    let max_side = if total >= 8 { (total - 6)/2 } else { 0 };
+    let mut boxes: Vec<(usize, usize)> = (1..(max_side + 1))
+        .flat_map(|w| (1..(max_side + 1)).map(move |h| (w, h)))
+        .filter(|&(w, h)| 2*(w + h) + 4 <= total && (!transposable || w <= h))
+        .collect();
+    boxes.sort_by_key(|&(w, h)| cmp::Reverse(w*h));
+    boxes
+}
+
+//  finds the maximum area the bundle can enclose. The area is bounded from above by wall_bound,
+//  and the search then tries every possible enclosed area (which beats the known layout,
+//  if given) together with every way to wall it off with the pieces, until an area reaches
+//  the bound. The wall around an area of an a*b bounding box takes at least 2*(a + b) + 4 cells,
+//  which (with the area needed to beat the known layout) bounds the boxes to try,
+//  and the squares the wall leaves over bound the cells of the pieces sticking out of it.
+//  The search is exhaustive, so it is only practical for the small bundles, or when the known
+//  layout already reaches the bound. The boxes wider than MAX_WIDTH get skipped,
+//  and the result is only proven if it reaches the bound, or none of them could beat it
+pub fn solve_exact<'a>(bundle: &'a Bundle, known: Option<Layout<'a>>) -> ExactResult<'a> {
+    let total: usize = bundle.iter().map(|v| v[0].squares.len()).sum();
+    let known = known.filter(|l| l.score() > 0.0);
+    let known_area = known.as_ref().map(|l| l.score() as usize).unwrap_or(0);
+    let mut nodes = 0;
+    let bound = wall_bound(bundle, known_area, &mut nodes);
+    println!("Upper bound on the area: {}", bound);
+    let mut target = known_area + 1;
+    let mut best = None;
+    let mut skipped = 0;    //  largest area of the boxes too wide to search
+
+    let margin = bundle.iter().flat_map(|v| v.iter())
+        .map(|s| cmp::max(s.width, s.height)).max().unwrap_or(0) + 1;
+    for (w, h) in list_boxes(total, is_transposable(bundle)) {
+        if target > bound { break; }
+        if w*h < target { continue; }
+        if w > MAX_WIDTH {
+            println!("Bounding box: {}x{} is too wide to search", w, h);
+            skipped = cmp::max(skipped, w*h);
+            continue;
+        }
+        println!("Bounding box: {}x{}, area to beat: {}", w, h, target - 1);
+        while target <= bound {
+            let stride = w as i32 + 2*margin;
+            let grid_h = h as i32 + 2*margin;
+            let mut search = Search {
+                bundle,
+                width: w,
+                height: h,
+                margin,
+                stride,
+                taken: vec![false; (stride*grid_h) as usize],
+                row_cells: vec![0; grid_h as usize],
+                rows: vec![],
+                used: vec![false; bundle.len()],
+                pos: vec![],
+                area: 0, wall: 0, waste: 0, squares: 0,
+                total,
+                min_wall: 2*(w + h) + 4,
+                target: &mut target,
+                nodes: &mut nodes,
+                found: None,
+            };
+            search.choose_row(0);
+            match search.found {
+                Some((layout, area)) => {
+                    target = area + 1;
+                    best = Some(Solution { core: layout.extract_core(), layout, score: area as f64 });
+                },
+                None => break,
+            }
+        }
+    }
+
+    let best = best.or_else(|| known.map(|layout| {
+        let score = layout.score();
+        let core = layout.extract_core();
+        Solution { layout, score, core }
+    }));
+    let proven = skipped < target || target > bound;
+    ExactResult { best, bound, proven, nodes }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polyomino::layout::parse_bundle;
+    use polyomino::verify::verify;
+
+    #[test]
+    fn test_is_connected() {
+        assert!(is_connected(&[0b110, 0b010]));
+        assert!(is_connected(&[0b010, 0b100]));
+        assert!(!is_connected(&[0b010, 0b000, 0b010]));
+    }
+
+    #[test]
+    fn test_certify() {
+        //  four I-trominoes around a 2x2 area, but with only three of them it leaks
+        let bundle = parse_bundle("OOO\n\nOOO\n\nOOO\n\nOOO\n", true, true).unwrap();
+        let h = bundle[0].iter().position(|s| s.width == 3).unwrap() as u16;
+        let v = bundle[0].iter().position(|s| s.height == 3).unwrap() as u16;
+        let pos = [Position { x: -1, y: -1, shape: 0, var: h }, Position { x: 2, y: -1, shape: 1, var: v },
+            Position { x: 0, y: 2, shape: 2, var: h }, Position { x: -1, y: 0, shape: 3, var: v }];
+        let core = [Vec2i::new(0, 0), Vec2i::new(1, 0), Vec2i::new(0, 1), Vec2i::new(1, 1)];
+        let layout = certify(&bundle, &core, &pos).unwrap();
+        assert_eq!(Some(4), verify(&layout).area);
+        assert!(certify(&bundle, &core, &pos[..3]).is_none());
+        //  a cell left empty next to the area
+        assert!(certify(&bundle, &core[..3], &pos).is_none());
+        //  the pieces must stay off the area
+        let mut covered = core.to_vec();
+        covered.push(Vec2i::new(-1, 0));
+        assert!(certify(&bundle, &covered, &pos).is_none());
+    }
+
+    #[test]
+    fn test_solve_exact() {
+        //  four I-trominoes can only make a 4x4 ring
+        let bundle = parse_bundle("OOO\n\nOOO\n\nOOO\n\nOOO\n", true, true).unwrap();
+        let res = solve_exact(&bundle, None);
+        assert_eq!(4, res.bound);
+        assert!(res.proven);
+        let best = res.best.unwrap();
+        assert_eq!(4.0, best.score);
+        assert!(verify(&best.layout).is_valid());
+
+        //  knowing the optimum, there is nothing to beat
+        let res = solve_exact(&bundle, Some(best.layout.clone()));
+        assert_eq!(4.0, res.best.unwrap().score);
+
+        //  a ring encloses its hole on its own
+        let bundle = parse_bundle("OOOO\nO  O\nOOOO\n\nO\n", true, true).unwrap();
+        let res = solve_exact(&bundle, None);
+        assert_eq!(2, res.bound);
+        let best = res.best.unwrap();
+        assert_eq!(2.0, best.score);
+        assert_eq!(Some(2), verify(&best.layout).area);
+
+        //  the boxes too wide for the search are listed as well, for it to tell they are skipped
+        let boxes = list_boxes(134, false);
+        assert!(boxes.contains(&(63, 1)) && boxes.contains(&(1, 63)));
+        assert!(boxes.windows(2).all(|b| b[0].0*b[0].1 >= b[1].0*b[1].1));
+        assert!(list_boxes(134, true).iter().all(|&(w, h)| w <= h));
+
+        //  too few squares to enclose anything
+        let bundle = parse_bundle("OO\n\nOOO\n", true, true).unwrap();
+        let res = solve_exact(&bundle, None);
+        assert_eq!(0, res.bound);
+        assert!(res.best.is_none());
+    }
+
+    #[test]
+    fn test_tetrominoes() {
+        let bundle = parse_bundle(include_str!("../../data/tetromino.txt"), true, true).unwrap();
+        let mut nodes = 0;
+        assert_eq!(9, wall_bound(&bundle, 0, &mut nodes));
+        let best = solve_exact(&bundle, None).best.unwrap();
+        assert_eq!(9.0, best.score);
+        assert_eq!(Some(9), verify(&best.layout).area);
+        assert!(verify(&best.layout).is_valid());
+    }
+}
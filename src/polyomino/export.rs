@@ -0,0 +1,296 @@
+// ------------------------------------------------------------------------------------------------
+// export.rs
+// ------------------------------------------------------------------------------------------------
+use std::fs::File;
+use std::io::prelude::*;
+use serde_json;
+
+use polyomino::math::{Vec2i};
+use polyomino::shape::{Shape, Transform, Transforms};
+use polyomino::grid::Grid;
+use polyomino::layout::{Bundle, Layout, Position, check_bundle};
+use polyomino::farm::{Solution};
+use polyomino::error::PolyfarmError;
+
+const EXPORT_VERSION : u32 = 1;
+
+//  Shape variant, as stored in the files
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct VariantData {
+    pub squares: Vec<[i32; 2]>,
+    pub transform: Transform,       //  relative to the shape as given in the shape file
+    #[serde(default, skip_serializing_if = "is_free")]
+    pub transforms: Transforms,     //  transformations allowed for the shape
+    #[serde(default, skip_serializing_if = "is_square")]
+    pub grid: Grid,                 //  square or hexagonal cells (in the axial coordinates)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+}
+
+//  Placed shape
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PositionData {
+    pub x: i32,
+    pub y: i32,
+    pub shape: u16,                 //  shape index
+    pub var: u16,                   //  shape variant index
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform: Option<Transform>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,       //  shape name, if given in the shape file
+}
+
+//  Single layout, with its score and enclosed cells (if known)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SolutionData {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    pub positions: Vec<PositionData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub core: Option<Vec<[i32; 2]>>,
+}
+
+//  Top-level JSON document. When the bundle is present, the variant indices
+//  are resolved against it, otherwise they index the bundle directly
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExportData {
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bundle: Option<Vec<Vec<VariantData>>>,
+    pub solutions: Vec<SolutionData>,
+}
+
+fn is_free(transforms: &Transforms) -> bool {
+    *transforms == Transforms::Free
+}
+
+fn is_square(grid: &Grid) -> bool {
+    *grid == Grid::Square
+}
+
+//  converts the bundle into plain square lists
+pub fn bundle_data(bundle: &Bundle) -> Vec<Vec<VariantData>> {
+    bundle.iter().map(|variants| {
+        variants.iter().map(|shape| VariantData {
+            squares: shape.squares.iter().map(|sq| [sq.x, sq.y]).collect(),
+            transform: shape.transform,
+            transforms: shape.transforms,
+            grid: shape.grid,
+            name: shape.name.clone(),
+            color: shape.color.clone(),
+        }).collect()
+    }).collect()
+}
+
+//  rebuilds the bundle from the square lists
+pub fn build_bundle(data: &[Vec<VariantData>]) -> Result<Bundle, PolyfarmError> {
+    if data.is_empty() {
+        return Err(PolyfarmError::EmptyBundle);
+    }
+    let bundle = data.iter().enumerate().map(|(i, variants)| {
+        if variants.is_empty() || variants.iter().any(|v| v.squares.is_empty()) {
+            return Err(PolyfarmError::InvalidData(format!(
+                "shape #{} has no squares or no variants", i + 1)));
+        }
+        if variants.iter().flat_map(|v| v.squares.iter()).any(|sq| sq[0] < 0 || sq[1] < 0) {
+            return Err(PolyfarmError::InvalidData(format!(
+                "shape #{} has negative square coordinates", i + 1)));
+        }
+        Ok(variants.iter().map(|v| {
+            let mut shape = Shape::with_grid(
+                v.squares.iter().map(|sq| Vec2i::new(sq[0], sq[1])).collect(), v.grid);
+            shape.transform = v.transform;
+            shape.transforms = v.transforms;
+            shape.name = v.name.clone();
+            shape.color = v.color.clone();
+            shape
+        }).collect())
+    }).collect::<Result<Bundle, PolyfarmError>>()?;
+    check_bundle(&bundle)?;
+    Ok(bundle)
+}
+
+//  returns the absolute coordinates of the enclosed cells
+fn core_cells(core: &Option<(Shape, Vec2i)>) -> Option<Vec<[i32; 2]>> {
+    core.as_ref().map(|&(ref shape, pos)| {
+        shape.squares.iter().map(|sq| [sq.x + pos.x, sq.y + pos.y]).collect()
+    })
+}
+
+//  converts the layout into its serializable form
+pub fn solution_data(layout: &Layout, score: f64, core: &Option<(Shape, Vec2i)>) -> SolutionData {
+    SolutionData {
+        score: Some(score),
+        positions: layout.pos.iter().map(|p| PositionData {
+            x: p.x, y: p.y, shape: p.shape, var: p.var,
+            transform: Some(layout.shape_by_pos(p).transform),
+            name: layout.shape_by_pos(p).name.clone(),
+        }).collect(),
+        core: core_cells(core),
+    }
+}
+
+//  writes the solutions, along with the bundle, into a JSON file
+pub fn write_json(path: &str, bundle: &Bundle, solutions: &[Solution]) -> Result<(), PolyfarmError> {
+    let data = ExportData {
+        version: EXPORT_VERSION,
+        bundle: Some(bundle_data(bundle)),
+        solutions: solutions.iter()
+            .map(|s| solution_data(&s.layout, s.score, &s.core)).collect(),
+    };
+    let mut file = File::create(path)?;
+    serde_json::to_writer(&mut file, &data)?;
+    writeln!(file)?;
+    Ok(())
+}
+
+//  rebuilds a single layout against the bundle, resolving the variants
+//  via the stored bundle (if any)
+pub fn build_layout<'a>(data: &SolutionData, stored: &Option<Vec<Vec<VariantData>>>,
+    bundle: &'a Bundle) -> Result<Layout<'a>, PolyfarmError>
+{
+    let mut pos = vec![];
+    for (i, p) in data.positions.iter().enumerate() {
+        let shape = p.shape as usize;
+        let mut var = p.var;
+        if let Some(ref stored) = *stored {
+            //  find the same variant in the given bundle
+            let squares = stored.get(shape)
+                .and_then(|variants| variants.get(p.var as usize))
+                .map(|v| v.squares.iter().map(|sq| Vec2i::new(sq[0], sq[1])).collect::<Vec<_>>())
+                .ok_or_else(|| PolyfarmError::InvalidData(format!(
+                    "position #{}: no such shape/variant in the stored bundle", i + 1)))?;
+            var = bundle.get(shape)
+                .and_then(|variants| variants.iter().position(|v| v.squares == squares))
+                .ok_or_else(|| PolyfarmError::InvalidData(format!(
+                    "position #{}: variant of shape #{} is not allowed in the bundle",
+                    i + 1, shape + 1)))? as u16;
+        }
+        pos.push(Position { x: p.x, y: p.y, shape: p.shape, var });
+    }
+    Layout::from_positions(bundle, pos)
+}
+
+//  rebuilds a single layout against the bundle, checking that it matches
+//  the stored bundle (if any), score and enclosed cells
+pub fn read_solution<'a>(data: &SolutionData, stored: &Option<Vec<Vec<VariantData>>>,
+    bundle: &'a Bundle) -> Result<Layout<'a>, PolyfarmError>
+{
+    let layout = build_layout(data, stored, bundle)?;
+    if let Some(score) = data.score {
+        let actual = layout.score();
+        if actual != score {
+            return Err(PolyfarmError::InvalidData(format!(
+                "stored score {} doesn't match the actual one {}", score, actual)));
+        }
+    }
+    if let Some(ref core) = data.core {
+        let mut core = core.clone();
+        core.sort();
+        let mut actual = core_cells(&layout.extract_core()).unwrap_or_default();
+        actual.sort();
+        if core != actual {
+            return Err(PolyfarmError::InvalidData(String::from(
+                "stored enclosed cells don't match the actual ones")));
+        }
+    }
+    Ok(layout)
+}
+
+//  parses the JSON document
+pub fn parse_json(input: &str) -> Result<ExportData, PolyfarmError> {
+    let data: ExportData = serde_json::from_str(input)?;
+    if data.version != EXPORT_VERSION {
+        return Err(PolyfarmError::InvalidData(format!(
+            "unsupported version {}", data.version)));
+    }
+    Ok(data)
+}
+
+//  parses the JSON document, rebuilding its layouts against the bundle
+pub fn read_layouts<'a>(input: &str, bundle: &'a Bundle) -> Result<Vec<Layout<'a>>, PolyfarmError> {
+    let data = parse_json(input)?;
+    data.solutions.iter().enumerate().map(|(i, s)| {
+        read_solution(s, &data.bundle, bundle).map_err(|e| match e {
+            PolyfarmError::InvalidData(msg) =>
+                PolyfarmError::InvalidData(format!("solution #{}: {}", i + 1, msg)),
+            e => e
+        })
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polyomino::layout::parse_bundle;
+
+    #[test]
+    fn test_export_roundtrip() {
+        //  a 4x4 ring of I-trominoes, enclosing 2x2 cells
+        let bundle = parse_bundle("OOO\n\nOOO\n\nO\nO\nO\n\nO\nO\nO\n", true, true).unwrap();
+        let pos = vec![
+            Position { x: 0, y: 0, shape: 0, var: 1 },
+            Position { x: 3, y: 0, shape: 2, var: 1 },
+            Position { x: 1, y: 3, shape: 1, var: 1 },
+            Position { x: 0, y: 1, shape: 3, var: 1 },
+        ];
+        let layout = Layout::from_positions(&bundle, pos).unwrap();
+        let core = layout.extract_core();
+        let score = layout.score();
+        assert_eq!(4.0, score);
+
+        let data = ExportData {
+            version: EXPORT_VERSION,
+            bundle: Some(bundle_data(&bundle)),
+            solutions: vec![solution_data(&layout, score, &core)],
+        };
+        let json = serde_json::to_string(&data).unwrap();
+        let layouts = read_layouts(&json, &bundle).unwrap();
+        assert_eq!(1, layouts.len());
+        assert_eq!(layout.pos, layouts[0].pos);
+
+        //  the same layout, checked against a bundle with a different variant order...
+        let fixed = parse_bundle("OOO\n\nOOO\n\nO\nO\nO\n\nO\nO\nO\n", false, false).unwrap();
+        let layouts = read_layouts(&json, &fixed).unwrap();
+        assert_eq!(0, layouts[0].pos[0].var);
+        assert_eq!(4.0, layouts[0].score());
+        //  ...or one where the variant is not allowed
+        let fixed = parse_bundle("O\nO\nO\n\nOOO\n\nO\nO\nO\n\nO\nO\nO\n", false, false).unwrap();
+        assert!(read_layouts(&json, &fixed).is_err());
+
+        //  the shape names go along
+        let input = "polyfarm-shapes 2\n[I3] color=#123\nOOO\n\nOOO\n\nO\nO\nO\n\nO\nO\nO\n";
+        let named = parse_bundle(input, true, true).unwrap();
+        let named_data = solution_data(&Layout::from_positions(&named, layout.pos.clone()).unwrap(),
+            score, &core);
+        assert_eq!(Some(String::from("I3")), named_data.positions[0].name);
+        assert_eq!(None, named_data.positions[1].name);
+        let rebuilt = build_bundle(&bundle_data(&named)).unwrap();
+        assert_eq!(Some(String::from("I3")), rebuilt[0][1].name);
+        assert_eq!(Some(String::from("123")), rebuilt[0][1].color);
+
+        //  the restrictions go along too, and the variants must keep to them
+        let input = "polyfarm-shapes 2\n[I3] transforms=fixed\nOOO\n\nOOO\n";
+        let mut restricted = bundle_data(&parse_bundle(input, true, true).unwrap());
+        assert_eq!(Transforms::Fixed, build_bundle(&restricted).unwrap()[0][0].transforms);
+        restricted[0][0].transform.rotation = 1;
+        assert!(build_bundle(&restricted).is_err());
+
+        //  and so does the grid, which has to be the same for all the shapes
+        let input = "polyfarm-shapes 2 grid=hex\nO O O\n\n O\nO O\n";
+        let mut hex = bundle_data(&parse_bundle(input, true, true).unwrap());
+        let rebuilt = build_bundle(&hex).unwrap();
+        assert!(rebuilt.iter().flat_map(|v| v.iter()).all(|s| s.grid == Grid::Hex));
+        assert_eq!((3, 2), (rebuilt[0].len(), rebuilt[1].len()));
+        hex[1][0].grid = Grid::Square;
+        assert!(build_bundle(&hex).is_err());
+
+        //  tampered score
+        let mut bad = data.clone();
+        bad.solutions[0].score = Some(2.0);
+        let json = serde_json::to_string(&bad).unwrap();
+        assert!(read_layouts(&json, &bundle).is_err());
+    }
+}
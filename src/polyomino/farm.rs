@@ -1,235 +1,508 @@
 // ------------------------------------------------------------------------------------------------
 // farm.rs
 // ------------------------------------------------------------------------------------------------
-use std::f64;
-use std::f64::consts::{PI};
+use std::fmt;
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
 use std::cmp;
-use rand::{Rng, SeedableRng, StdRng};
-use time::{PreciseTime};
+use std::f64;
+use std::collections::HashSet;
+use time::{PreciseTime, Duration};
+use serde_json;
 
+use polyomino::math::{Vec2i};
 use polyomino::shape::{Shape};
-use polyomino::layout::{Layout, Bundle, COFFS};
+use polyomino::grid::Grid;
+use polyomino::layout::{Layout, Bundle, check_bundle};
+use polyomino::error::PolyfarmError;
+use polyomino::checkpoint::Checkpoint;
+use polyomino::strategy::{Strategy, Context};
+use polyomino::genetic::Genetic;
+use polyomino::anneal::Anneal;
+use polyomino::islands::Islands;
+use polyomino::mutation::{MutationConfig, MutationStats, MutationRates, add_stats};
 
 const DISPLAY_ENTRIES : usize = 100;
-const COLORS : [&'static str; 12] = [
+const DIVERSITY_SAMPLE : usize = 100;
+const COLORS : [&str; 12] = [
     "8dd3c7", "ffffb3", "bebada", "fb8072", "80b1d3", "fdb462",
     "b3de69", "fccde5", "d9d9d9", "bc80bd", "ccebc5", "ffed6f"
 ];
 
-const MIN_FLIPS : usize = 2;
-const MAX_FLIPS : usize = 4;
 
+//  Search algorithm
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    Genetic,    //  generations of mutated (and crossed) layouts
+    Anneal,     //  independent chains of simulated annealing
+}
+
+//  How the annealing temperature goes down from the initial to the final one
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Schedule {
+    Geometric,  //  by the same factor every step
+    Linear,     //  by the same amount every step
+}
+
+//  Solver parameters
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FarmConfig {
+    pub out_file: String,       //  output HTML file path
+    pub seed: u32,              //  random seed
+    pub gen_size: u32,          //  generation size
+    pub max_iter: u32,          //  maximum iteration
+    pub elites: u32,            //  number of elites to preserve between generations
+    pub mut_percentage: u32,    //  percentage of generation to mutate
+    pub mut_attempts: u32,      //  mutation attempts per gene
+    pub cell_side: u32,         //  SVG cell side, pixels
+    pub threads: u32,           //  number of worker threads
+    pub checkpoint: Option<String>,     //  checkpoint file path, if any
+    pub checkpoint_interval: u32,       //  generations between checkpoints
+    pub seed_percentage: u32,   //  percentage of the first generation made of mutated seed layouts
+    pub crossover_rate: u32,    //  percentage of the mutated genes crossed with another one first
+    pub algorithm: Algorithm,   //  search algorithm
+    pub chains: u32,            //  number of annealing chains
+    pub anneal_steps: u32,      //  annealing steps per chain per iteration
+    pub temperature: f64,       //  initial annealing temperature
+    pub final_temperature: f64, //  annealing temperature at the end of every run
+    pub schedule: Schedule,     //  annealing temperature schedule
+    pub restarts: u32,          //  times to restart the annealing chains from their best layouts
+    pub islands: u32,           //  number of islands to split the generation into
+    pub migration_interval: u32,    //  generations between migrations of the islands' best layouts
+    pub migrants: u32,          //  layouts every island sends to the next one
+    pub time_limit: Option<f64>,        //  seconds to run for, if limited
+    pub stall_generations: Option<u32>, //  generations without improving the best score to stop after
+    pub target_score: Option<f64>,      //  score to stop at, if any
+    pub mutation: MutationConfig,       //  mutations to use and their weights
+}
 
+impl Default for FarmConfig {
+    fn default() -> FarmConfig {
+        FarmConfig {
+            out_file: String::from("output.html"),
+            seed: 42,
+            gen_size: 10000,
+            max_iter: 1000,
+            elites: 1,
+            mut_percentage: 90,
+            mut_attempts: 1000,
+            cell_side: 10,
+            threads: 1,
+            checkpoint: None,
+            checkpoint_interval: 10,
+            seed_percentage: 50,
+            crossover_rate: 0,
+            algorithm: Algorithm::Genetic,
+            chains: 4,
+            anneal_steps: 10000,
+            temperature: 0.5,
+            final_temperature: 0.02,
+            schedule: Schedule::Geometric,
+            restarts: 0,
+            islands: 1,
+            migration_interval: 10,
+            migrants: 1,
+            time_limit: None,
+            stall_generations: None,
+            target_score: None,
+            mutation: MutationConfig::default(),
+        }
+    }
+}
+
+impl FarmConfig {
+    //  reads the parameters from a JSON file, the missing ones taking the default values
+    pub fn load(path: &str) -> Result<FarmConfig, PolyfarmError> {
+        let mut contents = String::new();
+        File::open(path)?.read_to_string(&mut contents)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+    
+    //  number of layouts the search keeps (and saves into the checkpoints): 
+    //  the generation, or the current and the best layout of every annealing chain
+    pub fn population_size(&self) -> usize {
+        match self.algorithm {
+            Algorithm::Genetic => self.gen_size as usize,
+            Algorithm::Anneal => 2*self.chains as usize
+        }
+    }
+}
+
+//  What ended the search
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    MaxIter,        //  the maximum iteration was reached
+    TimeLimit,      //  the time ran out
+    Stalled,        //  the best score didn't improve for long enough
+    TargetScore,    //  the target score was reached
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StopReason::MaxIter => write!(f, "reached the maximum iteration"),
+            StopReason::TimeLimit => write!(f, "ran out of time"),
+            StopReason::Stalled => write!(f, "the best score hasn't improved"),
+            StopReason::TargetScore => write!(f, "reached the target score"),
+        }
+    }
+}
+
+//  Runs a search strategy: keeps the time, the statistics, the output and the checkpoints
 pub struct Farm<'a> {
-     bundle : &'a Bundle,
-     rng: StdRng,
-     gen_size: usize,
-     max_iter: u32,
-     elites: usize,
-     cell_side: u32,
-     mut_ratio: f64,
-     mut_attempts: u32,
-     out_file: String
+     ctx: Context<'a>,
+     strategy: Box<dyn Strategy<'a> + 'a>,
+     start: Option<Checkpoint>,
+     seeds: Vec<Layout<'a>>,
+}
+
+//  One of the best layouts found
+pub struct Solution<'a> {
+    pub layout: Layout<'a>,
+    pub score: f64,
+    pub core: Option<(Shape, Vec2i)>,    //  enclosed area and its position, if closed
 }
 
-struct Score {
-    score: f64,
-    layout: u32
+//  Statistics of a single generation
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct GenerationStats {
+    pub iteration: u32,
+    pub max_score: f64,
+    pub mean_score: f64,
+    pub time_ms: i64,
+    #[serde(default)]
+    pub crossovers: u32,                //  genes of the generation made by crossover
+    #[serde(default)]
+    pub crossover_improvements: u32,    //  how many of them scored above both parents
+    #[serde(default)]
+    pub mutations: Vec<MutationStats>,  //  how the mutations making the generation did
+    #[serde(default)]
+    pub mutation_rates: Option<MutationRates>,  //  adapted probabilities after the generation
+    #[serde(default)]
+    pub island_scores: Vec<f64>,        //  best scores of the islands, if any
+    #[serde(default)]
+    pub island_mutation_rates: Vec<MutationRates>,  //  adapted probabilities of the islands
+    #[serde(default)]
+    pub distinct_layouts: u32,          //  layouts kept that differ in the canonical form
+    #[serde(default)]
+    pub mean_distance: f64,             //  mean squares covered by only one of two layouts
+}
+
+//  Outcome of the search
+pub struct SolveResult<'a> {
+    pub best: Vec<Solution<'a>>,         //  distinct best layouts, by descending score
+    pub stats: Vec<GenerationStats>,     //  per-generation statistics
+    pub stop: StopReason,                //  what ended the search
 }
 
 impl<'a> Farm<'a> {
-    //  constructor
-    pub fn new(bundle: &'a Bundle, out_file: &str, 
-        seed: u32, gen_size: u32, max_iter: u32, 
-        elites: u32, mut_percentage: u32, mut_attempts: u32, 
-        cell_side: u32) -> Farm<'a> 
+    //  constructor, running the search algorithm chosen in the config
+    pub fn new(bundle: &'a Bundle, config: &FarmConfig) -> Result<Farm<'a>, PolyfarmError> {
+        let strategy: Box<dyn Strategy<'a> + 'a> = match config.algorithm {
+            Algorithm::Genetic if config.islands > 1 => Box::new(Islands::new()),
+            Algorithm::Genetic => Box::new(Genetic::new()),
+            Algorithm::Anneal => Box::new(Anneal::new())
+        };
+        Farm::with_strategy(bundle, config, strategy)
+    }
+    
+    //  constructor, running the given search strategy
+    pub fn with_strategy(bundle: &'a Bundle, config: &FarmConfig, 
+        strategy: Box<dyn Strategy<'a> + 'a>) -> Result<Farm<'a>, PolyfarmError> 
     {
-        let seed = seed as usize;
-        let seed: &[_] = &[seed, seed + 1, seed + 2, seed + 3];
-        Farm {
-            bundle: &bundle,
-            rng: SeedableRng::from_seed(seed),
-            gen_size: gen_size as usize,
-            max_iter: max_iter,
-            elites: elites as usize,
-            mut_ratio: (mut_percentage as f64)/100.0,
-            mut_attempts: mut_attempts,
-            cell_side: cell_side,
-            out_file: String::from(out_file)
+        if bundle.is_empty() {
+            return Err(PolyfarmError::EmptyBundle);
+        }
+        check_bundle(bundle)?;
+        if config.gen_size == 0 {
+            return Err(PolyfarmError::Config(String::from("generation size must be positive")));
+        }
+        if config.elites > config.gen_size {
+            return Err(PolyfarmError::Config(format!(
+                "number of elites ({}) exceeds generation size ({})", 
+                config.elites, config.gen_size)));
+        }
+        if config.mut_percentage > 100 {
+            return Err(PolyfarmError::Config(format!(
+                "mutation percentage ({}) exceeds 100", config.mut_percentage)));
+        }
+        if config.cell_side == 0 {
+            return Err(PolyfarmError::Config(String::from("cell side must be positive")));
+        }
+        if config.threads == 0 {
+            return Err(PolyfarmError::Config(String::from("number of threads must be positive")));
+        }
+        if config.checkpoint_interval == 0 {
+            return Err(PolyfarmError::Config(String::from("checkpoint interval must be positive")));
+        }
+        if config.seed_percentage > 100 {
+            return Err(PolyfarmError::Config(format!(
+                "seed percentage ({}) exceeds 100", config.seed_percentage)));
+        }
+        if config.crossover_rate > 100 {
+            return Err(PolyfarmError::Config(format!(
+                "crossover rate ({}) exceeds 100", config.crossover_rate)));
         }
+        if config.chains == 0 {
+            return Err(PolyfarmError::Config(String::from("number of chains must be positive")));
+        }
+        if config.anneal_steps == 0 {
+            return Err(PolyfarmError::Config(String::from("annealing steps must be positive")));
+        }
+        if !(config.final_temperature > 0.0 && config.final_temperature <= config.temperature) {
+            return Err(PolyfarmError::Config(format!(
+                "temperatures must be positive and decreasing, got {} to {}", 
+                config.temperature, config.final_temperature)));
+        }
+        if config.islands == 0 || config.islands > config.gen_size {
+            return Err(PolyfarmError::Config(format!(
+                "number of islands must be positive and not exceed generation size ({}), got {}",
+                config.gen_size, config.islands)));
+        }
+        if config.migration_interval == 0 {
+            return Err(PolyfarmError::Config(String::from("migration interval must be positive")));
+        }
+        if config.migrants > config.gen_size/config.islands {
+            return Err(PolyfarmError::Config(format!(
+                "number of migrants ({}) exceeds island size ({})", 
+                config.migrants, config.gen_size/config.islands)));
+        }
+        if let Some(limit) = config.time_limit {
+            if limit.is_nan() || limit <= 0.0 {
+                return Err(PolyfarmError::Config(format!(
+                    "time limit must be positive, got {}", limit)));
+            }
+        }
+        config.mutation.validate()?;
+        if config.stall_generations == Some(0) {
+            return Err(PolyfarmError::Config(String::from(
+                "number of stall generations must be positive")));
+        }
+        Ok(Farm {
+            ctx: Context::new(bundle, config),
+            strategy,
+            start: None,
+            seeds: vec![],
+        })
     }
     
-    //  finds approximate radius of a circle to lay out the shapes along
-    fn estimate_radius(bundle: &Bundle) -> f64 {
-        let len = bundle.iter().map(|v| v[0].estimate_len())
-            .fold(0.0, |sum, i| sum + i);
-        len/(2.0*PI)
+    //  constructor, continuing the search from a checkpoint
+    pub fn resume(bundle: &'a Bundle, checkpoint: Checkpoint) -> Result<Farm<'a>, PolyfarmError> {
+        checkpoint.validate(bundle)?;
+        let mut farm = Farm::new(bundle, &checkpoint.config)?;
+        farm.start = Some(checkpoint);
+        Ok(farm)
+    }
+    
+    //  adds known layouts to the first generation, along with their mutated copies
+    pub fn add_seeds(&mut self, layouts: &[Layout]) -> Result<(), PolyfarmError> {
+        let gen_size = self.ctx.config.gen_size as usize;
+        if self.seeds.len() + layouts.len() > gen_size {
+            return Err(PolyfarmError::Config(format!(
+                "number of seed layouts ({}) exceeds generation size ({})",
+                self.seeds.len() + layouts.len(), gen_size)));
+        }
+        for (i, layout) in layouts.iter().enumerate() {
+            //  make sure the positions refer to this bundle
+            let seed = Layout::from_positions(self.ctx.bundle, layout.pos.clone()).map_err(|e| match e {
+                PolyfarmError::InvalidData(msg) => 
+                    PolyfarmError::InvalidData(format!("seed layout #{}: {}", i + 1, msg)),
+                e => e
+            })?;
+            self.seeds.push(seed);
+        }
+        Ok(())
     }
         
     //  main grinding procedure    
-    pub fn grind(&mut self) {        
-        let radius = Farm::estimate_radius(&self.bundle);
-        
-        let mut gen0 = vec![Layout::new(&self.bundle); self.gen_size]; 
-        let mut gen1 = vec![Layout::new(&self.bundle); self.gen_size]; 
-        let mut scores = vec![];
+    pub fn grind(&mut self) -> Result<SolveResult<'a>, PolyfarmError> {        
+        let ctx = &self.ctx;
+        let strategy = &mut self.strategy;
+        let mut stats = vec![];
+        let mut it = 0;
+        let best;
+        let stop;
         
         let mut start_time = PreciseTime::now();
+        let run_start = start_time;
         
-        //  seed the first generation
-        for k in 0..self.gen_size {
-            let mut layout = &mut gen0[k];
-            layout.shuffle(&mut self.rng);
-            layout.arrange_circle(radius);
-            layout.center();
-            scores.push(Score{layout: 0, score: 0.0});
+        match self.start.take() {
+            Some(checkpoint) => {
+                //  continue from the saved population
+                let population = checkpoint.population.into_iter().map(|pos| {
+                    let mut layout = Layout::new(ctx.bundle);
+                    layout.pos = pos;
+                    layout
+                }).collect();
+                strategy.restore(ctx, population, checkpoint.state)?;
+                it = checkpoint.iteration;
+                stats = checkpoint.stats;
+            },
+            None => strategy.initialize(ctx, &self.seeds)
         }
         
-        //  iterate on generations
-        let mut gen_idx = 0;
-        let mut it = 0;
         loop {
-            let (prev_gen, cur_gen) = 
-                if gen_idx == 0 {(&gen0, &mut gen1)}
-                else {(&gen1, &mut gen0)};
-            gen_idx = 1 - gen_idx;
-            
-            for k in 0..self.gen_size {
-                scores[k] = Score{layout: k as u32, score: prev_gen[k].score()};
-            }
-            
-            scores.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+            let layouts = strategy.best();
             let cur_time = PreciseTime::now();
-            println!("Iteration: {}, max score: {}, time: {}ms", 
-                it, scores[0].score, start_time.to(cur_time).num_milliseconds());
+            let time_ms = start_time.to(cur_time).num_milliseconds();
             start_time = cur_time;
-            self.dump_layouts(&scores, prev_gen);
-            if it >= self.max_iter { break; }
-              
-            let mut ii = 0;
-            //  transfer the "elite" ones (making sure there is no duplicates)
-            for i in 0..self.gen_size {
-                let layout = &prev_gen[scores[i].layout as usize];
-                let is_dupe = cur_gen.iter().take(ii).any(|cl| cl == layout);
-                if !is_dupe {
-                    cur_gen[ii] = layout.clone();
-                    ii += 1;
-                    if ii == self.elites { break; }
+            let (distinct_layouts, mean_distance) = Farm::diversity(&layouts);
+            let mut gen_stats = GenerationStats {
+                iteration: it,
+                max_score: layouts[0].1,
+                mean_score: layouts.iter().map(|l| l.1).sum::<f64>()/(layouts.len() as f64),
+                time_ms,
+                crossovers: 0,
+                crossover_improvements: 0,
+                mutations: vec![],
+                mutation_rates: None,
+                island_scores: vec![],
+                island_mutation_rates: vec![],
+                distinct_layouts,
+                mean_distance,
+            };
+            let note = strategy.report(ctx, it, &mut gen_stats);
+            println!("Iteration: {}, max score: {}, distinct: {}, mean distance: {:.1}{}, time: {}ms", 
+                it, gen_stats.max_score, distinct_layouts, mean_distance, note, time_ms);
+            stats.push(gen_stats);
+            Farm::dump_layouts(&ctx.config, &layouts)?;
+            if let Some(reason) = Farm::stop_reason(&ctx.config, &stats, run_start.to(cur_time)) {
+                let config = &ctx.config;
+                match reason {
+                    StopReason::MaxIter => println!("Stopping: {}", reason),
+                    StopReason::TimeLimit => 
+                        println!("Stopping: {} ({}s)", reason, config.time_limit.unwrap()),
+                    StopReason::Stalled => println!("Stopping: {} for {} generations", 
+                        reason, config.stall_generations.unwrap()),
+                    StopReason::TargetScore => 
+                        println!("Stopping: {} ({})", reason, config.target_score.unwrap()),
                 }
-            }  
-            
-            //  apply mutations
-            let num_mut = cmp::min(self.gen_size - ii, 
-                ((self.gen_size as f64)*self.mut_ratio) as usize);
-            for _ in 0..num_mut {
-                //  pick the source gene, favoring the ones with higher scores
-                let pick_size = self.gen_size;
-                let idx = self.rng.gen_range(0, pick_size*pick_size + 1);
-                let idx = pick_size - (idx as f64).sqrt() as usize;
-                cur_gen[ii] = self.mutate_gene(&prev_gen[scores[idx].layout as usize]);
-                cur_gen[ii].center();
-                ii += 1;
-            }
-
-            //  pad the rest with the fresh ones
-            while ii < self.gen_size {
-                let mut layout = &mut cur_gen[ii];
-                layout.shuffle(&mut self.rng);
-                layout.arrange_circle(radius);
-                layout.center();
-                ii += 1;
+                stop = reason;
+                best = Farm::distinct_best(&layouts).iter().map(|&k| {
+                    let layout = layouts[k].0.clone();
+                    let core = layout.extract_core();
+                    Solution { layout, score: layouts[k].1, core }
+                }).collect();
+                break; 
             }
             it += 1;
+            strategy.step(ctx, it);
+            
+            if let Some(ref path) = ctx.config.checkpoint {
+                if it % ctx.config.checkpoint_interval == 0 {
+                    if let Some(population) = strategy.population() {
+                        let population = population.iter().map(|l| l.pos.clone()).collect();
+                        Checkpoint::new(&ctx.config, ctx.bundle, it, population, stats.clone(),
+                            strategy.state()).save(path)?;
+                    }
+                }
+            }
         }
+        Farm::print_mutations(&stats);
         println!("Done.");
+        Ok(SolveResult { best, stats, stop })
     }
     
-    fn mutate_gene<'c>(&mut self, layout: &Layout<'c>) -> Layout<'c> {
-        let mut max_score = -f64::MAX;
-        let nshapes = layout.pos.len();
-        let mut res = layout.clone();
-        for _ in 0..self.mut_attempts {
-            let num_flips = self.rng.gen_range(MIN_FLIPS, MAX_FLIPS + 1);
-            let mut cl = layout.clone();
-            for _ in 0..num_flips {
-                let mut_type = self.rng.gen_range(0, 3);
-                let pidx1 = self.rng.gen_range(0, nshapes);
-                let pidx2 = self.rng.gen_range(0, nshapes);
-                if mut_type == 0 {
-                    //  change variants on two shapes
-                    let shape1 = cl.pos[pidx1].shape as usize;
-                    let nvar1 = layout.bundle[shape1].len() as u16;
-                    cl.pos[pidx1].var = self.rng.gen_range(0, nvar1);
-                    
-                    let shape2 = cl.pos[pidx2].shape as usize;
-                    let nvar2 = layout.bundle[shape2].len() as u16;
-                    cl.pos[pidx2].var = self.rng.gen_range(0, nvar2);
-                } else if mut_type == 1 {
-                    //  randomly offset a section
-                    let offs = COFFS[self.rng.gen_range(0, COFFS.len())];
-                    for k in pidx1..(pidx2 + 1) {
-                        cl.pos[k].x += offs[0];
-                        cl.pos[k].y += offs[1];
-                    }
-                } else {
-                    //  swap two shapes
-                    let shape = cl.pos[pidx1].shape;
-                    cl.pos[pidx1].shape = cl.pos[pidx2].shape;
-                    cl.pos[pidx2].shape = shape;
-                    
-                    let var = cl.pos[pidx1].var;
-                    cl.pos[pidx1].var = cl.pos[pidx2].var;
-                    cl.pos[pidx2].var = var;
-                }
-            }
-            let score = cl.score();
-            if score > max_score {
-                max_score = score;
-                res = cl;
+    //  prints how the mutations did over the whole run
+    fn print_mutations(stats: &[GenerationStats]) {
+        let mut totals = vec![];
+        for s in stats {
+            add_stats(&mut totals, &s.mutations);
+        }
+        if totals.is_empty() { return; }
+        println!("Mutation successes: {}", totals.iter()
+            .map(|t| format!("{} {}/{}", t.name, t.successes, t.attempts))
+            .collect::<Vec<_>>().join(", "));
+    }
+    
+    //  checks the stopping criteria after the last iteration, 
+    //  given the statistics so far and the time since the start of the run
+    fn stop_reason(config: &FarmConfig, stats: &[GenerationStats], elapsed: Duration) 
+        -> Option<StopReason> 
+    {
+        let last = stats.last().unwrap();
+        if let Some(target) = config.target_score {
+            if last.max_score >= target { return Some(StopReason::TargetScore); }
+        }
+        if last.iteration >= config.max_iter {
+            return Some(StopReason::MaxIter);
+        }
+        if let Some(limit) = config.time_limit {
+            if (elapsed.num_milliseconds() as f64) >= limit*1000.0 { 
+                return Some(StopReason::TimeLimit); 
             }
         }
+        if let Some(n) = config.stall_generations {
+            //  the iteration the best score so far was first reached at
+            let best = stats.iter().map(|s| s.max_score).fold(f64::MIN, f64::max);
+            let since = stats.iter().find(|s| s.max_score == best).unwrap().iteration;
+            if last.iteration - since >= n { return Some(StopReason::Stalled); }
+        }
+        None
+    }
+    
+    //  returns indices (in the sorted layouts) of the top distinct ones,
+    //  telling them apart by the canonical form
+    fn distinct_best(layouts: &[(&Layout, f64)]) -> Vec<usize> {
+        let mut seen = HashSet::new();
+        let mut res = vec![];
+        for (k, l) in layouts.iter().enumerate() {
+            if res.len() == DISPLAY_ENTRIES { break; }
+            if seen.insert(l.0.canonical_hash()) { res.push(k); }
+        }
         res
     }
+
+    //  diversity of the layouts: the number of distinct ones (by the canonical form),
+    //  and the mean number of squares covered by only one of the two layouts
+    //  over the pairs of a sample, spread evenly over the ranking
+    fn diversity(layouts: &[(&Layout, f64)]) -> (u32, f64) {
+        let distinct: HashSet<u64> = layouts.iter().map(|l| l.0.canonical_hash()).collect();
+        let n = cmp::min(DIVERSITY_SAMPLE, layouts.len());
+        let sample: Vec<Vec<Vec2i>> = (0..n)
+            .map(|k| layouts[k*layouts.len()/n].0.canonical_cells()).collect();
+        let mut total = 0;
+        for i in 0..n {
+            for j in 0..i {
+                total += Layout::cell_distance(&sample[i], &sample[j]);
+            }
+        }
+        let npairs = n*n.saturating_sub(1)/2;
+        let mean = if npairs == 0 { 0.0 } else { (total as f64)/(npairs as f64) };
+        (distinct.len() as u32, mean)
+    }
     
-    fn dump_layouts(&self, scores: &Vec<Score>, gen : &Vec<Layout>) {
-        let mut file = File::create(&self.out_file).unwrap();
-        writeln!(file, "<div>").unwrap();
-        let ndisp = cmp::min(DISPLAY_ENTRIES, self.gen_size);
-        let mut k = 0;
-        let mut cur_pos = 0;
-        while cur_pos < ndisp && k < self.gen_size {
-            let layout = &gen[scores[k].layout as usize];
-            let is_dupe = scores.iter().take(k).any(|s| {
-                layout == &gen[s.layout as usize]
-            });
-            k += 1;
-            if is_dupe { continue; }
-            cur_pos += 1;
-            
-            self.dump_svg(&mut file, layout);
+    fn dump_layouts(config: &FarmConfig, layouts: &[(&Layout, f64)]) -> io::Result<()> {
+        let mut file = File::create(&config.out_file)?;
+        writeln!(file, "<div>")?;
+        for k in Farm::distinct_best(layouts) {
+            Farm::dump_svg(config, &mut file, layouts[k].0)?;
         } 
-        writeln!(file, "</div>").unwrap();
+        writeln!(file, "</div>")
     }
     
-    fn dump_svg(&self, file : &mut File, layout: &Layout) {
-        let (lt, rb) = layout.bounds();
-        let w = (rb.x - lt.x + 1) as u32;
-        let h = (rb.y - lt.y + 1) as u32;
+    fn dump_svg(config: &FarmConfig, file : &mut File, layout: &Layout) -> io::Result<()> {
+        let grid = layout.grid();
+        let ((left, top), (right, bottom)) = picture_bounds(layout);
 
-        let cs = self.cell_side as f64;
+        let cs = config.cell_side as f64;
 
         //  svg header
+        let rendering = if grid == Grid::Square { "crispEdges" } else { "geometricPrecision" };
         writeln!(file, r###"
         <svg xmlns="http://www.w3.org/2000/svg"
             xmlns:xlink="http://www.w3.org/1999/xlink"
-            shape-rendering="crispEdges"
+            shape-rendering="{}"
             width="{}" height="{}">
-        "###, (w as f64)*cs, (h as f64)*cs).unwrap();
+        "###, rendering, px((right - left)*cs), px((bottom - top)*cs))?;
 
-        //  defs
-        writeln!(file, r###"
+        //  defs (the hexagons of the core are outlined one by one instead)
+        if grid == Grid::Square {
+            writeln!(file, r###"
             <defs>
               <pattern id="squares" patternUnits="userSpaceOnUse" x="0" y="0" width="{}" height="{}">
                 <g style="fill:none; stroke:#dde; stroke-width:1">
@@ -237,74 +510,571 @@ impl<'a> Farm<'a> {
                 </g>
               </pattern>
             </defs>
-            "###, cs, cs, cs, cs, cs, cs).unwrap();
+            "###, cs, cs, cs, cs, cs, cs)?;
+        }
 
         //  styles
-        let styles = r###"
+        let core_style = match grid {
+            Grid::Square => "fill: url(#squares) #fff;",
+            Grid::Hex => "fill: #fff; stroke:#dde; stroke-width:1;",
+        };
+        writeln!(file, r###"
             <style>
               /* <![CDATA[ */
-                .core { fill: url(#squares) #fff; }
-                .caption { fill: #aae; font-family:Arial; font-size:25px; font-weight:bold;
-                  dominant-baseline:central; text-anchor:middle; }
-                .shape { stroke:#8888aa; stroke-width:1; opacity:1; }
+                .core {{ {} }}
+                .caption {{ fill: #aae; font-family:Arial; font-size:25px; font-weight:bold;
+                  dominant-baseline:central; text-anchor:middle; }}
+                .shape {{ stroke:#8888aa; stroke-width:1; opacity:1; }}
               /* ]]> */
-            </style>"###;
-
-        writeln!(file, "{}", styles).unwrap();
+            </style>"###, core_style)?;
 
         //  the core (if present)
-        match layout.extract_core() {
-            Some((shape, pos)) => {
-                let x = (pos.x - lt.x) as f64;
-                let y = (pos.y - lt.y) as f64;
-                let dx = x*cs;
-                let dy = y*cs;
-                
-                //  the core path
-                let path = self.gen_shape_path(&shape);
-                write!(file, r###"
-                <path class="core" transform="translate({},{})" d="{}"></path>"###,
-                    dx, dy, path).unwrap();
-                
-                //  the caption         
-                let tx = dx + (shape.width as f64)*cs*0.5;
-                let ty = dy + (shape.height as f64)*cs*0.5;
-                write!(file, r###"
-                <text class="caption" x="{}" y="{}">{}</text>"###,
-                    tx, ty, shape.squares.len()).unwrap();              
-            },
-            None => ()
+        if let Some((shape, pos)) = layout.extract_core() {
+            let (x, y) = grid.point(pos.x as f64, pos.y as f64);
+            let dx = (x - left)*cs;
+            let dy = (y - top)*cs;
+            
+            //  the core path
+            let path = Farm::gen_shape_path(config, &shape);
+            write!(file, r###"
+            <path class="core" transform="translate({},{})" d="{}"></path>"###,
+                px(dx), px(dy), path)?;
+            
+            //  the caption, in the middle of the core's bounding box
+            let ((l, t), (r, b)) = corner_bounds(grid, &shape.squares);
+            let tx = dx + (l + r)*cs*0.5;
+            let ty = dy + (t + b)*cs*0.5;
+            write!(file, r###"
+            <text class="caption" x="{}" y="{}">{}</text>"###,
+                px(tx), px(ty), shape.squares.len())?;              
         }
         
         //  the shapes
         for pos in &layout.pos {
             let shape = layout.shape_by_pos(pos);
-            let x = (pos.x - lt.x) as f64;
-            let y = (pos.y - lt.y) as f64;
-            let dx = x*cs;
-            let dy = y*cs;
+            let (x, y) = grid.point(pos.x as f64, pos.y as f64);
+            let dx = (x - left)*cs;
+            let dy = (y - top)*cs;
             
-            let path = self.gen_shape_path(shape);
-            let color = COLORS[(pos.shape as usize)%COLORS.len()];
+            let path = Farm::gen_shape_path(config, shape);
+            let color = match shape.color {
+                Some(ref color) => color.as_str(),
+                None => COLORS[(pos.shape as usize)%COLORS.len()]
+            };
+            let title = match shape.name {
+                Some(ref name) => format!("<title>{}</title>", escape_xml(name)),
+                None => String::new()
+            };
             write!(file, r###"
-            <path fill="#{}" class="shape" transform="translate({},{})" d="{}"></path>"###, 
-                color, dx, dy, path).unwrap();
+            <path fill="#{}" class="shape" transform="translate({},{})" d="{}">{}</path>"###, 
+                color, px(dx), px(dy), path, title)?;
         }
 
         writeln!(file, r###"
-        </svg>"### ).unwrap();
+        </svg>"### )
     }
     
-    fn gen_shape_path(&self, shape: &Shape) -> String {
+    //  a closed polygon per cell (the squares or the hexagons)
+    fn gen_shape_path(config: &FarmConfig, shape: &Shape) -> String {
         use std::fmt::Write;
-        let cs = self.cell_side as f64;
+        let cs = config.cell_side as f64;
+        let corners = shape.grid.corners();
         let mut res = String::new();
         for sq in &shape.squares {
-            let (x, y) = ((sq.x as f64)*cs, (sq.y as f64)*cs);
-            let (x1, y1) = (x + cs, y + cs);
-            write!(&mut res, "M{},{} L{},{} L{},{} L{},{} Z ", 
-                x, y, x1, y, x1, y1, x, y1).unwrap();
+            let (x, y) = shape.grid.point(sq.x as f64, sq.y as f64);
+            for (k, &(cx, cy)) in corners.iter().enumerate() {
+                write!(&mut res, "{}{},{} ", if k == 0 { "M" } else { "L" }, 
+                    px((x + cx)*cs), px((y + cy)*cs)).unwrap();
+            }
+            res.push_str("Z ");
         }
         res
     }
-}
\ No newline at end of file
+}
+
+//  the top left and the bottom right corners of the cells on the plane
+fn corner_bounds(grid: Grid, cells: &[Vec2i]) -> ((f64, f64), (f64, f64)) {
+    let corners = grid.corners();
+    let start = ((f64::MAX, f64::MAX), (f64::MIN, f64::MIN));
+    cells.iter().fold(start, |((l, t), (r, b)), c| {
+        let (x, y) = grid.point(c.x as f64, c.y as f64);
+        corners.iter().fold(((l, t), (r, b)), |((l, t), (r, b)), &(cx, cy)| 
+            ((l.min(x + cx), t.min(y + cy)), (r.max(x + cx), b.max(y + cy))))
+    })
+}
+
+//  the area of the layout's picture, in cells: the squares get a cell of margin 
+//  to the right and below, the hexagons fit tightly
+fn picture_bounds(layout: &Layout) -> ((f64, f64), (f64, f64)) {
+    match layout.grid() {
+        Grid::Square => {
+            let (lt, rb) = layout.bounds();
+            ((lt.x as f64, lt.y as f64), ((rb.x + 1) as f64, (rb.y + 1) as f64))
+        },
+        grid => corner_bounds(grid, &layout.cells())
+    }
+}
+
+//  rounds the pixel coordinate to keep the SVG short (the squares' ones are whole anyway)
+fn px(v: f64) -> f64 {
+    (v*100.0).round()/100.0
+}
+
+//  escapes the text to put into the SVG
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use polyomino::layout::parse_bundle;
+    use polyomino::sketch::parse_sketch;
+    use polyomino::strategy::{Strategy, Context};
+
+    const TETROMINOES: &str = include_str!("../../data/tetromino.txt");
+
+    //  the files a test writes into the temp dir, removed once it's done
+    struct TestFiles {
+        paths: Vec<PathBuf>,
+    }
+
+    impl Drop for TestFiles {
+        fn drop(&mut self) {
+            for path in &self.paths { let _ = fs::remove_file(path); }
+        }
+    }
+
+    //  default configuration for the named test, with the output going into the temp dir
+    //  (and the checkpoint, see checkpoint_file), starting without any files left over
+    fn test_config(name: &str) -> (FarmConfig, TestFiles) {
+        let out_file = env::temp_dir().join(format!("polyfarm_test_{}.html", name));
+        let config = FarmConfig {
+            out_file: String::from(out_file.to_str().unwrap()), ..FarmConfig::default()
+        };
+        let files = TestFiles { paths: vec![out_file, PathBuf::from(checkpoint_file(&config))] };
+        for path in &files.paths { let _ = fs::remove_file(path); }
+        (config, files)
+    }
+
+    fn checkpoint_file(config: &FarmConfig) -> String {
+        config.out_file.replace(".html", ".json")
+    }
+
+    //  runs the search through with a checkpoint at the given iteration (the only one, 
+    //  so it has to be past the half of max_iter), then resumes from it, checking that 
+    //  both end up with the same layouts. Returns both results (the checkpoint stays in 
+    //  checkpoint_file)
+    fn assert_resumes<'a>(bundle: &'a Bundle, config: &FarmConfig, at: u32) 
+        -> (SolveResult<'a>, SolveResult<'a>) 
+    {
+        assert!(at < config.max_iter && 2*at > config.max_iter);
+        let checkpointed = FarmConfig {
+            checkpoint: Some(checkpoint_file(config)), checkpoint_interval: at, ..config.clone()
+        };
+        let full = Farm::new(bundle, &checkpointed).unwrap().grind().unwrap();
+        let mut checkpoint = Checkpoint::load(&checkpoint_file(config)).unwrap();
+        assert_eq!(at, checkpoint.iteration);
+        checkpoint.config.checkpoint = None;
+        let resumed = Farm::resume(bundle, checkpoint).unwrap().grind().unwrap();
+        assert_eq!(full.stats.len(), resumed.stats.len());
+        assert_eq!(full.best.len(), resumed.best.len());
+        for (s1, s2) in full.best.iter().zip(&resumed.best) {
+            assert_eq!(s1.score, s2.score);
+            assert_eq!(s1.layout.pos, s2.layout.pos);
+        }
+        (full, resumed)
+    }
+
+    //  checks that the adapted mutation rates are bit for bit the same in both runs,
+    //  and that the ones saved in the checkpoint are those of the generation it was taken at
+    fn assert_same_rates(full: &SolveResult, resumed: &SolveResult, config: &FarmConfig) {
+        for (s1, s2) in full.stats.iter().zip(&resumed.stats) {
+            assert!(s1.mutation_rates.is_some());
+            assert_eq!(s1.mutation_rates, s2.mutation_rates);
+        }
+        let checkpoint = Checkpoint::load(&checkpoint_file(config)).unwrap();
+        let saved: MutationRates = serde_json::from_value(checkpoint.state.unwrap()).unwrap();
+        let at = checkpoint.iteration as usize;
+        assert_eq!(full.stats[at].mutation_rates.as_ref(), Some(&saved));
+    }
+
+    #[test]
+    fn test_grind_result() {
+        let bundle = parse_bundle(TETROMINOES, true, true).unwrap();
+        let (config, _files) = test_config("grind_result");
+        let config = FarmConfig { gen_size: 50, max_iter: 3, mut_attempts: 20, ..config };
+        let mut farm = Farm::new(&bundle, &config).unwrap();
+        let res = farm.grind().unwrap();
+
+        assert_eq!(4, res.stats.len());
+        assert_eq!(3, res.stats[3].iteration);
+        assert!(!res.best.is_empty());
+        assert_eq!(res.stats[3].max_score, res.best[0].score);
+        let names: Vec<&str> = res.stats[3].mutations.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(vec!["variant", "offset", "swap"], names);
+        assert!(res.stats[3].mutations.iter().all(|m| m.attempts > 0 && m.successes <= m.attempts));
+        for (i, sol) in res.best.iter().enumerate() {
+            assert_eq!(sol.score, sol.layout.score());
+            assert_eq!(sol.score > 0.0, sol.core.is_some());
+            if i > 0 { assert!(sol.score <= res.best[i - 1].score); }
+        }
+        if let Some((ref core, _)) = res.best[0].core {
+            assert_eq!(res.best[0].score as usize, core.squares.len());
+        }
+        //  no two of the best layouts are the same up to a move, turn or reflection
+        let hashes: HashSet<u64> = res.best.iter().map(|s| s.layout.canonical_hash()).collect();
+        assert_eq!(res.best.len(), hashes.len());
+        for st in &res.stats {
+            assert!(st.distinct_layouts >= res.best.len() as u32 && st.distinct_layouts <= 50);
+            assert!(st.mean_distance > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_dump_names() {
+        let bundle = parse_bundle("polyfarm-shapes 2\n[I <3>] color=#123456\nOOO\n\nOO\n", 
+            true, true).unwrap();
+        let (config, _files) = test_config("dump_names");
+        let layout = Layout::new(&bundle);
+        Farm::dump_layouts(&config, &[(&layout, 0.0)]).unwrap();
+        let mut html = String::new();
+        File::open(&config.out_file).unwrap().read_to_string(&mut html).unwrap();
+        assert!(html.contains("fill=\"#123456\""));
+        assert!(html.contains("<title>I &lt;3&gt;</title>"));
+        assert_eq!(1, html.matches("<title>").count());
+    }
+
+    #[test]
+    fn test_grind_restricted() {
+        let input = "polyfarm-shapes 2\n[L] transforms=one-sided\nOOO\nO\n\n[T] transforms=fixed\n\
+            OOO\n.O.\n\n[S]\nOO\n.OO\n\n[I] transforms=fixed\nOOOO\n";
+        let bundle = parse_bundle(input, true, true).unwrap();
+        let (config, _files) = test_config("grind_restricted");
+        let config = FarmConfig {
+            gen_size: 20, max_iter: 5, mut_attempts: 10, crossover_rate: 30, threads: 2, ..config
+        };
+        let anneal = FarmConfig { algorithm: Algorithm::Anneal, chains: 2, anneal_steps: 100, 
+            ..config.clone() };
+        for config in &[config, anneal] {
+            let res = Farm::new(&bundle, config).unwrap().grind().unwrap();
+            for p in res.best.iter().flat_map(|s| s.layout.pos.iter()) {
+                let shape = &bundle[p.shape as usize][p.var as usize];
+                assert!(shape.transforms.allows(shape.transform));
+                if p.shape == 1 || p.shape == 3 { assert_eq!(0, p.var); }
+            }
+        }
+
+        //  a bundle with a disallowed variant is refused
+        let mut bad = parse_bundle(input, true, true).unwrap();
+        bad[1][0].transform.rotation = 2;
+        assert!(Farm::new(&bad, &FarmConfig::default()).is_err());
+    }
+
+    #[test]
+    fn test_grind_hex() {
+        let bundle = parse_bundle(include_str!("../../data/tetrahex.txt"), true, true).unwrap();
+        let (config, _files) = test_config("grind_hex");
+        let config = FarmConfig {
+            gen_size: 30, max_iter: 10, mut_attempts: 20, crossover_rate: 30,
+            mutation: MutationConfig { rotate: 1, refit: 1, ..MutationConfig::default() },
+            ..config
+        };
+        let (res, _) = assert_resumes(&bundle, &config, 6);
+        assert!(res.best[0].score > 0.0);
+        for sol in &res.best {
+            assert_eq!(sol.score, sol.layout.score());
+            if let Some((ref core, _)) = sol.core {
+                assert_eq!(Grid::Hex, core.grid);
+                assert_eq!(sol.score as usize, core.squares.len());
+            }
+        }
+        //  the cells are drawn as hexagons
+        let mut html = String::new();
+        File::open(&config.out_file).unwrap().read_to_string(&mut html).unwrap();
+        assert!(html.contains("geometricPrecision") && !html.contains("id=\"squares\""));
+        let path = html.split(" d=\"").nth(2).unwrap();
+        assert_eq!(6, path.split('Z').next().unwrap().matches(',').count());
+    }
+
+    #[test]
+    fn test_diversity() {
+        let bundle = parse_bundle(TETROMINOES, true, true).unwrap();
+        let mut layout = Layout::new(&bundle);
+        layout.arrange_circle(5.0);
+        let mut moved = layout.clone();
+        moved.center();
+        let other = Layout { bundle: &bundle, pos: layout.pos.iter().rev().cloned().collect() };
+        let layouts = vec![(&layout, 1.0), (&moved, 1.0), (&other, 1.0)];
+        assert_eq!((1, 0.0), Farm::diversity(&layouts));
+        assert_eq!(vec![0], Farm::distinct_best(&layouts));
+
+        let mut apart = layout.clone();
+        apart.pos[0].x += 1;
+        let layouts = vec![(&layout, 1.0), (&apart, 1.0)];
+        let (distinct, mean) = Farm::diversity(&layouts);
+        assert_eq!(2, distinct);
+        assert!(mean > 0.0);
+        assert_eq!((1, 0.0), Farm::diversity(&layouts[..1]));
+    }
+
+    #[test]
+    fn test_grind_threads_deterministic() {
+        let bundle = parse_bundle(TETROMINOES, true, true).unwrap();
+        let (config, _files) = test_config("grind_threads");
+        let config = FarmConfig { gen_size: 40, max_iter: 3, mut_attempts: 10, threads: 3, ..config };
+        let res1 = Farm::new(&bundle, &config).unwrap().grind().unwrap();
+        let res2 = Farm::new(&bundle, &config).unwrap().grind().unwrap();
+        assert_eq!(res1.best.len(), res2.best.len());
+        for (s1, s2) in res1.best.iter().zip(&res2.best) {
+            assert_eq!(s1.score, s2.score);
+            assert!(s1.layout == s2.layout);
+        }
+    }
+
+    #[test]
+    fn test_grind_crossover() {
+        let bundle = parse_bundle(TETROMINOES, true, true).unwrap();
+        let (config, _files) = test_config("grind_crossover");
+        let config = FarmConfig {
+            gen_size: 40, max_iter: 3, mut_attempts: 10, threads: 2, crossover_rate: 50, ..config
+        };
+        let res1 = Farm::new(&bundle, &config).unwrap().grind().unwrap();
+        let res2 = Farm::new(&bundle, &config).unwrap().grind().unwrap();
+        for (s1, s2) in res1.best.iter().zip(&res2.best) {
+            assert!(s1.layout == s2.layout);
+        }
+        for (i, st) in res1.stats.iter().enumerate() {
+            //  the first generation isn't bred
+            assert_eq!(i > 0, st.crossovers > 0);
+            assert!(st.crossover_improvements <= st.crossovers);
+            assert_eq!(st.crossovers, res2.stats[i].crossovers);
+        }
+        assert!(Farm::new(&bundle, &FarmConfig { crossover_rate: 101, ..config }).is_err());
+    }
+
+    #[test]
+    fn test_grind_islands() {
+        let bundle = parse_bundle(TETROMINOES, true, true).unwrap();
+        let (config, _files) = test_config("grind_islands");
+        let mutation = MutationConfig { adaptive: true, ..MutationConfig::default() };
+        let config = FarmConfig {
+            gen_size: 31, max_iter: 5, mut_attempts: 10, threads: 2, crossover_rate: 30,
+            islands: 3, migration_interval: 2, migrants: 2, mutation, ..config
+        };
+        let full = Farm::new(&bundle, &config).unwrap().grind().unwrap();
+        let again = Farm::new(&bundle, &config).unwrap().grind().unwrap();
+        for (st, st2) in full.stats.iter().zip(&again.stats) {
+            assert_eq!(3, st.island_scores.len());
+            assert_eq!(st.max_score, st.island_scores.iter().cloned().fold(f64::MIN, f64::max));
+            assert_eq!(st.island_scores, st2.island_scores);
+            assert_eq!(3, st.island_mutation_rates.len());
+            assert_eq!(st.island_mutation_rates, st2.island_mutation_rates);
+            for rates in &st.island_mutation_rates {
+                assert!((rates.mutations.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+            }
+        }
+        assert_ne!(full.stats[0].island_mutation_rates, full.stats[4].island_mutation_rates);
+
+        //  the islands (with their mutation rates) carry over the checkpoints
+        let (full, resumed) = assert_resumes(&bundle, &config, 3);
+        assert_eq!(31, Checkpoint::load(&checkpoint_file(&config)).unwrap().population.len());
+        assert_eq!(full.stats[5].island_scores, resumed.stats[5].island_scores);
+        assert_eq!(full.stats[5].island_mutation_rates, resumed.stats[5].island_mutation_rates);
+
+        assert!(Farm::new(&bundle, &FarmConfig { islands: 0, ..config.clone() }).is_err());
+        assert!(Farm::new(&bundle, &FarmConfig { islands: 32, ..config.clone() }).is_err());
+        assert!(Farm::new(&bundle, &FarmConfig { migration_interval: 0, ..config.clone() }).is_err());
+        assert!(Farm::new(&bundle, &FarmConfig { migrants: 11, ..config }).is_err());
+    }
+
+    #[test]
+    fn test_anneal() {
+        let bundle = parse_bundle(TETROMINOES, true, true).unwrap();
+        let (config, _files) = test_config("anneal");
+        let config = FarmConfig {
+            algorithm: Algorithm::Anneal, chains: 3, anneal_steps: 300, max_iter: 4, restarts: 1,
+            ..config
+        };
+        let res1 = Farm::new(&bundle, &config).unwrap().grind().unwrap();
+        assert_eq!(5, res1.stats.len());
+        assert!(res1.best.len() <= 3);
+        for sol in &res1.best {
+            assert_eq!(sol.score, sol.layout.score());
+        }
+        
+        //  the chains don't depend on the threads they run on
+        let threads = FarmConfig { threads: 2, schedule: Schedule::Linear, ..config.clone() };
+        let res2 = Farm::new(&bundle, &threads).unwrap().grind().unwrap();
+        let res3 = Farm::new(&bundle, &FarmConfig { threads: 1, ..threads }).unwrap()
+            .grind().unwrap();
+        assert_eq!(res2.best.len(), res3.best.len());
+        for (s2, s3) in res2.best.iter().zip(&res3.best) {
+            assert!(s2.layout == s3.layout);
+        }
+        
+        //  and can be resumed from a checkpoint
+        assert_resumes(&bundle, &config, 3);
+        assert_eq!(6, Checkpoint::load(&checkpoint_file(&config)).unwrap().population.len());
+        
+        let hot = FarmConfig { final_temperature: 1.0, ..config };
+        assert!(Farm::new(&bundle, &hot).is_err());
+    }
+
+    //  keeps the fresh layouts of the first iteration, counting the steps
+    struct Idle<'a> {
+        layouts: Vec<(Layout<'a>, f64)>,
+        steps: u32,
+    }
+
+    impl<'a> Strategy<'a> for Idle<'a> {
+        fn initialize(&mut self, ctx: &Context<'a>, _seeds: &[Layout<'a>]) {
+            self.layouts = (0..3).map(|k| {
+                let layout = ctx.fresh_layout(&mut ctx.rng(0, k));
+                let score = layout.score();
+                (layout, score)
+            }).collect();
+            self.layouts.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        }
+
+        fn step(&mut self, _ctx: &Context<'a>, it: u32) {
+            self.steps += 1;
+            assert_eq!(self.steps, it);
+        }
+
+        fn best(&self) -> Vec<(&Layout<'a>, f64)> {
+            self.layouts.iter().map(|l| (&l.0, l.1)).collect()
+        }
+    }
+
+    #[test]
+    fn test_custom_strategy() {
+        let bundle = parse_bundle(TETROMINOES, true, true).unwrap();
+        let (config, _files) = test_config("custom_strategy");
+        let config = FarmConfig {
+            max_iter: 4, checkpoint: Some(checkpoint_file(&config)), checkpoint_interval: 2, ..config
+        };
+        let strategy = Box::new(Idle { layouts: vec![], steps: 0 });
+        let res = Farm::with_strategy(&bundle, &config, strategy).unwrap().grind().unwrap();
+        assert_eq!(5, res.stats.len());
+        assert!(res.best.len() <= 3);
+        assert_eq!(res.stats[0].max_score, res.stats[4].max_score);
+        //  the strategy can't be saved, so there are no checkpoints
+        assert!(!Path::new(&checkpoint_file(&config)).exists());
+    }
+
+    #[test]
+    fn test_grind_stop() {
+        let bundle = parse_bundle(TETROMINOES, true, true).unwrap();
+        let (config, _files) = test_config("grind_stop");
+        let config = FarmConfig { gen_size: 20, max_iter: 3, mut_attempts: 5, ..config };
+        let res = Farm::new(&bundle, &config).unwrap().grind().unwrap();
+        assert_eq!(StopReason::MaxIter, res.stop);
+        
+        let target = FarmConfig { max_iter: 1000, target_score: Some(1.0), ..config.clone() };
+        let res = Farm::new(&bundle, &target).unwrap().grind().unwrap();
+        assert_eq!(StopReason::TargetScore, res.stop);
+        assert!(res.best[0].score >= 1.0);
+        assert!(res.stats.iter().rev().skip(1).all(|s| s.max_score < 1.0));
+        
+        let stalled = FarmConfig { max_iter: 1000, stall_generations: Some(3), ..config.clone() };
+        let res = Farm::new(&bundle, &stalled).unwrap().grind().unwrap();
+        assert_eq!(StopReason::Stalled, res.stop);
+        let n = res.stats.len();
+        assert!(res.stats[n - 4].max_score >= res.stats[n - 1].max_score);
+        assert!(res.stats[..n - 4].iter().all(|s| s.max_score < res.stats[n - 4].max_score));
+        
+        let timed = FarmConfig { max_iter: 1000000, time_limit: Some(0.05), ..config.clone() };
+        let res = Farm::new(&bundle, &timed).unwrap().grind().unwrap();
+        assert_eq!(StopReason::TimeLimit, res.stop);
+        
+        assert!(Farm::new(&bundle, &FarmConfig { time_limit: Some(0.0), ..config.clone() }).is_err());
+        assert!(Farm::new(&bundle, &FarmConfig { stall_generations: Some(0), ..config }).is_err());
+    }
+
+    #[test]
+    fn test_grind_adaptive() {
+        let bundle = parse_bundle(TETROMINOES, true, true).unwrap();
+        let (config, _files) = test_config("grind_adaptive");
+        let mutation = MutationConfig { adaptive: true, reverse: 1, ..MutationConfig::default() };
+        let config = FarmConfig { gen_size: 30, max_iter: 4, mut_attempts: 10, threads: 2, mutation, ..config };
+        let full = Farm::new(&bundle, &config).unwrap().grind().unwrap();
+        let again = Farm::new(&bundle, &config).unwrap().grind().unwrap();
+        for (s1, s2) in full.stats.iter().zip(&again.stats) {
+            assert_eq!(s1.mutation_rates, s2.mutation_rates);
+        }
+        let rates = full.stats[4].mutation_rates.as_ref().unwrap();
+        assert!((rates.mutations.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert_ne!(full.stats[0].mutation_rates, full.stats[4].mutation_rates);
+        
+        //  the rates carry over the checkpoints exactly
+        let (full, resumed) = assert_resumes(&bundle, &config, 3);
+        assert_same_rates(&full, &resumed, &config);
+    }
+
+    #[test]
+    fn test_anneal_adaptive() {
+        let bundle = parse_bundle(TETROMINOES, true, true).unwrap();
+        let (config, _files) = test_config("anneal_adaptive");
+        let mutation = MutationConfig { adaptive: true, reverse: 1, ..MutationConfig::default() };
+        let config = FarmConfig {
+            algorithm: Algorithm::Anneal, chains: 3, anneal_steps: 300, max_iter: 4, restarts: 1,
+            mutation, ..config
+        };
+        let full = Farm::new(&bundle, &config).unwrap().grind().unwrap();
+        assert_ne!(full.stats[0].mutation_rates, full.stats[4].mutation_rates);
+
+        let (full, resumed) = assert_resumes(&bundle, &config, 3);
+        assert_same_rates(&full, &resumed, &config);
+    }
+
+    #[test]
+    fn test_config_load() {
+        let path = env::temp_dir().join("polyfarm_test_config_load.json");
+        let _files = TestFiles { paths: vec![path.clone()] };
+        File::create(&path).unwrap()
+            .write_all(br#"{"gen_size": 7, "mutation": {"max_flips": 6, "reverse": 2}}"#).unwrap();
+        let config = FarmConfig::load(path.to_str().unwrap()).unwrap();
+        assert_eq!(7, config.gen_size);
+        assert_eq!(FarmConfig::default().max_iter, config.max_iter);
+        assert_eq!((2, 6), (config.mutation.min_flips, config.mutation.max_flips));
+        assert_eq!((1, 2), (config.mutation.variant, config.mutation.reverse));
+    }
+
+    #[test]
+    fn test_grind_seeds() {
+        let bundle = parse_bundle(TETROMINOES, true, true).unwrap();
+        let (config, _files) = test_config("grind_seeds");
+        let seed = parse_sketch(".BB..\nABBCC\nA...C\nA...C\nA...E\nDDDEE\n.D.E.\n", &bundle).unwrap();
+        let config = FarmConfig { gen_size: 20, max_iter: 0, mut_attempts: 5, ..config };
+        let mut farm = Farm::new(&bundle, &config).unwrap();
+        farm.add_seeds(std::slice::from_ref(&seed)).unwrap();
+        let res = farm.grind().unwrap();
+        assert_eq!(9.0, seed.score());
+        assert!(res.best[0].score >= 9.0);
+        
+        //  too many seeds
+        let mut farm = Farm::new(&bundle, &FarmConfig { gen_size: 1, ..config.clone() }).unwrap();
+        assert!(farm.add_seeds(&[seed.clone(), seed.clone()]).is_err());
+    }
+
+    #[test]
+    fn test_grind_resume() {
+        let bundle = parse_bundle(TETROMINOES, true, true).unwrap();
+        let (config, _files) = test_config("grind_resume");
+        let config = FarmConfig { gen_size: 30, max_iter: 5, mut_attempts: 10, threads: 2, ..config };
+        assert_resumes(&bundle, &config, 3);
+        
+        //  the checkpoint (made at iteration 3) has the bundle in it as well
+        let checkpoint = Checkpoint::load(&checkpoint_file(&config)).unwrap();
+        assert_eq!(3, checkpoint.stats.len());
+        let resumed_bundle = checkpoint.bundle().unwrap();
+        assert_eq!(bundle.len(), resumed_bundle.len());
+        for (v1, v2) in bundle.iter().zip(&resumed_bundle) {
+            assert!(v1.len() == v2.len() && v1.iter().zip(v2).all(|(s1, s2)| s1 == s2));
+        }
+    }
+}
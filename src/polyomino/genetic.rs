@@ -0,0 +1,234 @@
+// ------------------------------------------------------------------------------------------------
+// genetic.rs
+// ------------------------------------------------------------------------------------------------
+use std::cmp;
+use std::mem;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use serde_json;
+use serde_json::Value;
+use rand::{Rng, StdRng};
+
+use polyomino::layout::{Layout};
+use polyomino::farm::GenerationStats;
+use polyomino::strategy::{Strategy, Context};
+use polyomino::mutation::{mutate, MutationCounts, MutationRates};
+use polyomino::error::PolyfarmError;
+
+struct Score {
+    score: f64,
+    layout: u32
+}
+
+//  Genetic algorithm: every generation keeps the elites, and fills the rest with
+//  the mutated (and possibly crossed) copies of the better scoring layouts
+#[derive(Default)]
+pub struct Genetic<'a> {
+    gen: Vec<Layout<'a>>,       //  the current generation
+    next: Vec<Layout<'a>>,      //  the next generation, as it's being made
+    scores: Vec<Score>,         //  scores of the current generation, by descending score
+    crossovers: u32,            //  genes of the current generation made by crossover
+    improvements: u32,          //  how many of them scored above both parents
+    mutations: MutationCounts,  //  how the mutations making the current generation did
+    rates: Option<MutationRates>,   //  adapted probabilities of the mutations, if adaptive
+}
+
+impl<'a> Genetic<'a> {
+    //  constructor
+    pub fn new() -> Genetic<'a> {
+        Genetic::default()
+    }
+
+    //  scores the current generation
+    fn score(&mut self, ctx: &Context<'a>) {
+        let gen = &self.gen;
+        self.scores = (0..gen.len()).map(|_| Score{layout: 0, score: 0.0}).collect();
+        ctx.run_parallel(&mut self.scores, |_, offset, chunk| {
+            for (i, sc) in chunk.iter_mut().enumerate() {
+                let k = offset + i;
+                *sc = Score{layout: k as u32, score: gen[k].score()};
+            }
+        });
+        self.scores.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+    }
+
+    //  replaces the worst layouts of the generation with the given ones
+    pub fn immigrate(&mut self, ctx: &Context<'a>, layouts: Vec<Layout<'a>>) {
+        let worst: Vec<usize> = self.scores.iter().rev().take(layouts.len())
+            .map(|s| s.layout as usize).collect();
+        for (k, layout) in worst.into_iter().zip(layouts) {
+            self.gen[k] = layout;
+        }
+        self.score(ctx);
+    }
+
+    //  crosses the layouts, keeping a random section of the first one's chain
+    fn cross_genes<R: Rng>(layout: &Layout<'a>, other: &Layout<'a>, rng: &mut R) -> Layout<'a> {
+        let n = layout.pos.len();
+        if n < 2 { return layout.clone(); }
+        let start = rng.gen_range(0, n);
+        let len = rng.gen_range(1, n);
+        layout.crossover(other, start, len)
+    }
+}
+
+impl<'a> Strategy<'a> for Genetic<'a> {
+    //  seeds the first generation: the known layouts go first,
+    //  then their mutated copies, the rest is random
+    fn initialize(&mut self, ctx: &Context<'a>, seeds: &[Layout<'a>]) {
+        let gen_size = ctx.config.gen_size as usize;
+        let nseeds = seeds.len();
+        let num_copies = if nseeds == 0 { 0 } else {
+            cmp::min(gen_size - nseeds, gen_size*(ctx.config.seed_percentage as usize)/100)
+        };
+        let mutation = &ctx.config.mutation;
+        self.rates = MutationRates::start(mutation, None);
+        let rates = self.rates.as_ref();
+        let mutations = Mutex::new(MutationCounts::default());
+        self.gen = vec![Layout::new(ctx.bundle); gen_size];
+        ctx.run_parallel(&mut self.gen, |chunk_idx, offset, layouts| {
+            let mut rng = ctx.rng(0, chunk_idx + 1);
+            let mut counts = MutationCounts::default();
+            for (i, layout) in layouts.iter_mut().enumerate() {
+                let k = offset + i;
+                if k < nseeds + num_copies {
+                    *layout = seeds[k%nseeds].clone();
+                    if k >= nseeds {
+                        *layout = mutate(layout, ctx.config.mut_attempts, mutation, rates,
+                            &mut rng, &mut counts);
+                    }
+                    layout.center();
+                } else {
+                    *layout = ctx.fresh_layout(&mut rng);
+                }
+            }
+            mutations.lock().unwrap().add(&counts);
+        });
+        self.mutations = mutations.into_inner().unwrap();
+        self.score(ctx);
+    }
+
+    fn step(&mut self, ctx: &Context<'a>, it: u32) {
+        let gen_size = self.gen.len();
+        let (prev_gen, scores, rates) = (&self.gen, &self.scores, self.rates.as_ref());
+        let cur_gen = &mut self.next;
+        cur_gen.resize(gen_size, prev_gen[0].clone());
+
+        let mut ii = 0;
+        //  transfer the "elite" ones (making sure there is no duplicates, up to the canonical form)
+        let mut elites = HashSet::new();
+        for sc in scores {
+            let layout = &prev_gen[sc.layout as usize];
+            if elites.insert(layout.canonical_hash()) {
+                cur_gen[ii] = layout.clone();
+                ii += 1;
+                if ii == ctx.config.elites as usize { break; }
+            }
+        }
+
+        //  pick the source genes to mutate, favoring the ones with higher scores
+        let mut_ratio = (ctx.config.mut_percentage as f64)/100.0;
+        let num_mut = cmp::min(gen_size - ii, ((gen_size as f64)*mut_ratio) as usize);
+        let mut rng = ctx.rng(it, 0);
+        let pick = |rng: &mut StdRng| {
+            let idx = rng.gen_range(0, gen_size*gen_size + 1);
+            &scores[gen_size - cmp::max(1, (idx as f64).sqrt() as usize)]
+        };
+        let parents: Vec<&Score> = (0..num_mut).map(|_| pick(&mut rng)).collect();
+        //  and the ones to cross them with
+        let crossover_rate = ctx.config.crossover_rate;
+        let mates: Vec<Option<&Score>> = parents.iter().map(|_| {
+            if crossover_rate > 0 && rng.gen_range(0, 100) < crossover_rate {
+                Some(pick(&mut rng))
+            } else { None }
+        }).collect();
+
+        //  apply crossovers and mutations, padding the rest with the fresh ones
+        let crossovers = AtomicUsize::new(0);
+        let improvements = AtomicUsize::new(0);
+        let mutation = &ctx.config.mutation;
+        let mutations = Mutex::new(MutationCounts::default());
+        ctx.run_parallel(&mut cur_gen[ii..], |chunk_idx, offset, layouts| {
+            let mut rng = ctx.rng(it, chunk_idx + 1);
+            let mut counts = MutationCounts::default();
+            for (i, layout) in layouts.iter_mut().enumerate() {
+                match parents.get(offset + i) {
+                    Some(parent) => {
+                        let source = &prev_gen[parent.layout as usize];
+                        *layout = match mates[offset + i] {
+                            Some(mate) => {
+                                let child = Genetic::cross_genes(source,
+                                    &prev_gen[mate.layout as usize], &mut rng);
+                                crossovers.fetch_add(1, Ordering::Relaxed);
+                                if child.score() > parent.score.max(mate.score) {
+                                    improvements.fetch_add(1, Ordering::Relaxed);
+                                }
+                                mutate(&child, ctx.config.mut_attempts, mutation, rates,
+                                    &mut rng, &mut counts)
+                            },
+                            None => mutate(source, ctx.config.mut_attempts, mutation, rates,
+                                &mut rng, &mut counts)
+                        };
+                        layout.center();
+                    },
+                    None => *layout = ctx.fresh_layout(&mut rng)
+                }
+            }
+            mutations.lock().unwrap().add(&counts);
+        });
+        self.mutations = mutations.into_inner().unwrap();
+        self.crossovers = crossovers.load(Ordering::Relaxed) as u32;
+        self.improvements = improvements.load(Ordering::Relaxed) as u32;
+        if let Some(ref mut rates) = self.rates {
+            rates.update(mutation, &self.mutations);
+        }
+        mem::swap(&mut self.gen, &mut self.next);
+        self.score(ctx);
+    }
+
+    fn best(&self) -> Vec<(&Layout<'a>, f64)> {
+        self.scores.iter().map(|s| (&self.gen[s.layout as usize], s.score)).collect()
+    }
+
+    fn report(&self, ctx: &Context<'a>, _it: u32, stats: &mut GenerationStats) -> String {
+        stats.mutations = self.mutations.stats(&ctx.config.mutation);
+        stats.mutation_rates = self.rates.clone();
+        stats.crossovers = self.crossovers;
+        stats.crossover_improvements = self.improvements;
+        let mut note = String::new();
+        if self.crossovers > 0 {
+            note += &format!(", crossovers: {} ({} improved on both parents)", 
+                self.crossovers, self.improvements);
+        }
+        if let Some(ref rates) = self.rates {
+            note += &format!(", {}", rates.describe(&ctx.config.mutation));
+        }
+        note
+    }
+
+    fn population(&self) -> Option<Vec<&Layout<'a>>> {
+        Some(self.gen.iter().collect())
+    }
+
+    //  the adapted rates of the mutations
+    fn state(&self) -> Option<Value> {
+        self.rates.as_ref().and_then(|rates| serde_json::to_value(rates).ok())
+    }
+
+    fn restore(&mut self, ctx: &Context<'a>, population: Vec<Layout<'a>>, 
+        state: Option<Value>) -> Result<(), PolyfarmError>
+    {
+        self.gen = population;
+        let last: Option<MutationRates> = match state {
+            Some(state) => serde_json::from_value(state)?,
+            None => None
+        };
+        self.rates = MutationRates::start(&ctx.config.mutation, last.as_ref());
+        self.crossovers = 0;
+        self.improvements = 0;
+        self.mutations = MutationCounts::default();
+        self.score(ctx);
+        Ok(())
+    }
+}
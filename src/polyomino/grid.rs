@@ -0,0 +1,141 @@
+// ------------------------------------------------------------------------------------------------
+// grid.rs
+// ------------------------------------------------------------------------------------------------
+//  The cells of the shapes are either squares or hexagons. The hexagons ("pointy top" ones,
+//  in staggered rows) use the axial coordinates: x goes along the row, y down and to the right,
+//  so that the shapes still move around by adding the coordinates, and keep their masks.
+use polyomino::math::{Vec2i};
+use polyomino::shape::OFFS;
+use polyomino::layout::COFFS;
+
+//  neighbor offsets on the hexagonal grid, clockwise from the right one
+pub const HEX_OFFS: [[i32; 2]; 6] = [[1, 0], [0, 1], [-1, 1], [-1, 0], [0, -1], [1, -1]];
+
+//  Kind of the cells
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Grid {
+    #[default]
+    Square,     //  polyominoes
+    Hex,        //  polyhexes
+}
+
+impl Grid {
+    pub fn name(self) -> &'static str {
+        match self {
+            Grid::Square => "square",
+            Grid::Hex => "hex",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Grid> {
+        [Grid::Square, Grid::Hex].iter().find(|g| g.name() == name).cloned()
+    }
+
+    //  offsets of the cells sharing a side with a cell
+    pub fn neighbors(self) -> &'static [[i32; 2]] {
+        match self {
+            Grid::Square => &OFFS,
+            Grid::Hex => &HEX_OFFS,
+        }
+    }
+
+    //  offsets of the cells an enclosed area spreads to (the squares leak through the corners)
+    pub fn fill_neighbors(self) -> &'static [[i32; 2]] {
+        match self {
+            Grid::Square => &COFFS,
+            Grid::Hex => &HEX_OFFS,
+        }
+    }
+
+    //  number of the rotation steps making the full turn (by 90 or 60 degrees)
+    pub fn rotations(self) -> u8 {
+        match self {
+            Grid::Square => 4,
+            Grid::Hex => 6,
+        }
+    }
+
+    //  the cell turned clockwise by a rotation step around the origin
+    pub fn turn(self, p: Vec2i) -> Vec2i {
+        match self {
+            Grid::Square => Vec2i::new(-p.y, p.x),
+            Grid::Hex => Vec2i::new(-p.y, p.x + p.y),
+        }
+    }
+
+    //  the cell mirrored horizontally around the origin
+    pub fn mirror(self, p: Vec2i) -> Vec2i {
+        match self {
+            Grid::Square => Vec2i::new(-p.x, p.y),
+            Grid::Hex => Vec2i::new(-p.x - p.y, p.y),
+        }
+    }
+
+    //  number of the steps between the cells, offset by (dx, dy)
+    pub fn distance(self, dx: i32, dy: i32) -> i32 {
+        match self {
+            Grid::Square => dx.abs() + dy.abs(),
+            Grid::Hex => (dx.abs() + dy.abs() + (dx + dy).abs())/2,
+        }
+    }
+
+    //  position of the cell (the top left corner of its bounding box) on the plane,
+    //  the cells being a unit wide
+    pub fn point(self, x: f64, y: f64) -> (f64, f64) {
+        match self {
+            Grid::Square => (x, y),
+            Grid::Hex => (x + y*0.5, y*3.0f64.sqrt()*0.5),
+        }
+    }
+
+    //  corners of the cell at the origin on the plane, clockwise from the top (left) one
+    pub fn corners(self) -> Vec<(f64, f64)> {
+        match self {
+            Grid::Square => vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            Grid::Hex => {
+                let r = 1.0/3.0f64.sqrt();
+                vec![(0.5, 0.0), (1.0, r*0.5), (1.0, r*1.5), (0.5, r*2.0), (0.0, r*1.5), (0.0, r*0.5)]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grid_transforms() {
+        for &grid in &[Grid::Square, Grid::Hex] {
+            let p = Vec2i::new(2, -1);
+            let mut q = p;
+            for k in 0..grid.rotations() {
+                if k > 0 { assert!(q != p); }
+                q = grid.turn(q);
+            }
+            assert_eq!(p, q);
+            assert_eq!(p, grid.mirror(grid.mirror(p)));
+            //  the neighbors go clockwise, and are a step away
+            let offs = grid.neighbors();
+            for (k, o) in offs.iter().enumerate() {
+                assert_eq!(1, grid.distance(o[0], o[1]));
+                let next = offs[(k + 1)%offs.len()];
+                assert!(grid.turn(Vec2i::new(o[0], o[1])) == next);
+            }
+            //  mirroring after the turn is turning the other way after the mirroring
+            let turned = grid.mirror(grid.turn(p));
+            let mut back = grid.mirror(p);
+            for _ in 1..grid.rotations() { back = grid.turn(back); }
+            assert_eq!(turned, back);
+        }
+        assert_eq!(3, Grid::Hex.distance(2, 1));
+        assert_eq!(2, Grid::Hex.distance(2, -1));
+        //  the neighbors are a unit apart on the plane
+        for o in HEX_OFFS.iter() {
+            let (x, y) = Grid::Hex.point(o[0] as f64, o[1] as f64);
+            assert!(((x*x + y*y).sqrt() - 1.0).abs() < 1e-9);
+        }
+        assert_eq!(Some(Grid::Hex), Grid::from_name("hex"));
+    }
+}
@@ -0,0 +1,139 @@
+// ------------------------------------------------------------------------------------------------
+// islands.rs
+// ------------------------------------------------------------------------------------------------
+use std::cmp;
+use serde_json::Value;
+
+use polyomino::layout::{Layout};
+use polyomino::farm::GenerationStats;
+use polyomino::strategy::{Strategy, Context};
+use polyomino::genetic::Genetic;
+use polyomino::mutation::add_stats;
+use polyomino::error::PolyfarmError;
+
+//  A part of the generation, evolved on its own
+struct Island<'a> {
+    ctx: Context<'a>,           //  the island's part of the generation and of the threads
+    genetic: Genetic<'a>,
+}
+
+//  Island model: the generation is split into sub-populations evolved independently
+//  (in parallel, as far as the threads go), and every few generations the best layouts
+//  of every island migrate to the next one in a ring, replacing its worst ones
+#[derive(Default)]
+pub struct Islands<'a> {
+    islands: Vec<Island<'a>>,
+}
+
+impl<'a> Islands<'a> {
+    //  constructor
+    pub fn new() -> Islands<'a> {
+        Islands::default()
+    }
+
+    //  splits the generation and the threads between the islands
+    fn setup(&mut self, ctx: &Context<'a>) {
+        let (gen_size, n) = (ctx.config.gen_size, ctx.config.islands);
+        let threads = cmp::max(1, ctx.config.threads/n);
+        self.islands = (0..n).map(|k| {
+            let size = gen_size/n + if k < gen_size%n { 1 } else { 0 };
+            Island { ctx: ctx.island(k as usize + 1, size, threads), genetic: Genetic::new() }
+        }).collect();
+    }
+
+    //  moves copies of the best layouts of every island over to the next one
+    fn migrate(&mut self, migrants: usize) {
+        let emigrants: Vec<Vec<Layout<'a>>> = self.islands.iter().map(|island| {
+            island.genetic.best().iter().take(migrants).map(|l| l.0.clone()).collect()
+        }).collect();
+        let n = self.islands.len();
+        for (k, layouts) in emigrants.into_iter().enumerate() {
+            let island = &mut self.islands[(k + 1)%n];
+            island.genetic.immigrate(&island.ctx, layouts);
+        }
+    }
+}
+
+impl<'a> Strategy<'a> for Islands<'a> {
+    //  every island starts from the known layouts (as many as fit)
+    fn initialize(&mut self, ctx: &Context<'a>, seeds: &[Layout<'a>]) {
+        self.setup(ctx);
+        ctx.run_parallel(&mut self.islands, |_, _, chunk| {
+            for island in chunk {
+                let nseeds = cmp::min(seeds.len(), island.ctx.config.gen_size as usize);
+                island.genetic.initialize(&island.ctx, &seeds[..nseeds]);
+            }
+        });
+    }
+
+    fn step(&mut self, ctx: &Context<'a>, it: u32) {
+        ctx.run_parallel(&mut self.islands, |_, _, chunk| {
+            for island in chunk {
+                island.genetic.step(&island.ctx, it);
+            }
+        });
+        if it.is_multiple_of(ctx.config.migration_interval) {
+            self.migrate(ctx.config.migrants as usize);
+        }
+    }
+
+    fn best(&self) -> Vec<(&Layout<'a>, f64)> {
+        let mut res: Vec<_> = self.islands.iter().flat_map(|island| island.genetic.best()).collect();
+        res.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        res
+    }
+
+    //  the statistics add up over the islands, with the best score (and the adapted mutation
+    //  rates) of every island logged
+    fn report(&self, _ctx: &Context<'a>, it: u32, stats: &mut GenerationStats) -> String {
+        let mut notes = String::new();
+        for (k, island) in self.islands.iter().enumerate() {
+            let mut island_stats = GenerationStats::default();
+            island.genetic.report(&island.ctx, it, &mut island_stats);
+            stats.crossovers += island_stats.crossovers;
+            stats.crossover_improvements += island_stats.crossover_improvements;
+            add_stats(&mut stats.mutations, &island_stats.mutations);
+            stats.island_scores.push(island.genetic.best()[0].1);
+            if let Some(rates) = island_stats.mutation_rates {
+                notes += &format!(", island {}: {}", k + 1,
+                    rates.describe(&island.ctx.config.mutation));
+                stats.island_mutation_rates.push(rates);
+            }
+        }
+        let mut note = format!(", islands: {}", stats.island_scores.iter()
+            .map(|s| s.to_string()).collect::<Vec<_>>().join("/"));
+        if stats.crossovers > 0 {
+            note += &format!(", crossovers: {} ({} improved on both parents)",
+                stats.crossovers, stats.crossover_improvements);
+        }
+        note + &notes
+    }
+
+    //  the islands' generations one after another
+    fn population(&self) -> Option<Vec<&Layout<'a>>> {
+        Some(self.islands.iter().flat_map(|island| island.genetic.population().unwrap()).collect())
+    }
+
+    //  the adapted mutation rates of every island
+    fn state(&self) -> Option<Value> {
+        Some(Value::Array(self.islands.iter()
+            .map(|island| island.genetic.state().unwrap_or(Value::Null)).collect()))
+    }
+
+    fn restore(&mut self, ctx: &Context<'a>, mut population: Vec<Layout<'a>>,
+        state: Option<Value>) -> Result<(), PolyfarmError>
+    {
+        self.setup(ctx);
+        let mut states = match state {
+            Some(Value::Array(states)) => states,
+            _ => vec![]
+        };
+        states.resize(self.islands.len(), Value::Null);
+        for (island, state) in self.islands.iter_mut().zip(states) {
+            let rest = population.split_off(island.ctx.config.gen_size as usize);
+            island.genetic.restore(&island.ctx, population, Some(state))?;
+            population = rest;
+        }
+        Ok(())
+    }
+}
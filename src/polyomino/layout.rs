@@ -2,16 +2,21 @@
 // layout.rs
 // ------------------------------------------------------------------------------------------------
 use std::f64;
-use std::i32;
 use std::cmp;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use rand::{Rng};
 use super::math::*;
-use super::shape::{Shape, OFFS};
+use super::shape::{Shape};
+use super::grid::Grid;
+use super::error::PolyfarmError;
+use super::shapefile::parse_shape_file;
 
 pub const COFFS: [[i32; 2]; 8] = [[1, 0], [0, 1], [-1, 0], [0, -1], [1, -1], [1, 1], [-1, 1], [-1, -1]];
 const MAX_DIST : f64 = 1000.0;
 
 #[derive(PartialEq, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Overlap {
     Overlap, // have a common square
     Border, // have a common edge
@@ -22,7 +27,7 @@ pub enum Overlap {
 pub type Bundle = Vec<Vec<Shape>>;
 
 //  Polyomino "intance" (both geometrical and variation)
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Position {
     pub x : i32,
     pub y : i32,
@@ -43,14 +48,50 @@ impl<'a> PartialEq<Layout<'a>> for Layout<'a> {
     }
 }
 
-pub fn parse_bundle(input: &str, mirrored: bool, rotated: bool) -> Bundle {
-    input.split("\n\n")
-    .map(|s| Shape::parse(s).variants(mirrored, rotated))
-    .collect()
+//  parses a bundle from a shape file (in either format, see shapefile.rs),
+//  the flags restricting the variants of all the shapes (on top of their own restrictions)
+pub fn parse_bundle(input: &str, mirrored: bool, rotated: bool) 
+    -> Result<Bundle, PolyfarmError> 
+{
+    let defs = parse_shape_file(input)?;
+    if defs.is_empty() {
+        return Err(PolyfarmError::EmptyBundle);
+    }
+    let mut bundle = vec![];
+    for def in &defs {
+        for _ in 0..def.count {
+            bundle.push(def.shape.variants(mirrored, rotated));
+        }
+    }
+    Ok(bundle)
+}
+
+//  checks that every shape in the bundle has variants, each one its shape is allowed to take,
+//  and that all of them are on the same grid
+pub fn check_bundle(bundle: &Bundle) -> Result<(), PolyfarmError> {
+    let grid = match bundle.iter().flat_map(|v| v.first()).next() {
+        Some(v) => v.grid,
+        None => return Ok(()),
+    };
+    for (i, variants) in bundle.iter().enumerate() {
+        if variants.is_empty() {
+            return Err(PolyfarmError::InvalidData(format!("shape #{} has no variants", i + 1)));
+        }
+        if variants.iter().any(|v| v.grid != grid) {
+            return Err(PolyfarmError::InvalidData(format!(
+                "shape #{} is not on the {} grid as the first one", i + 1, grid.name())));
+        }
+        if let Some(v) = variants.iter().find(|v| !v.transforms.allows(v.transform)) {
+            return Err(PolyfarmError::InvalidData(format!(
+                "shape #{} is {}, but has a variant mirrored: {}, rotated {} times", 
+                i + 1, v.transforms.name(), v.transform.mirrored, v.transform.rotation)));
+        }
+    }
+    Ok(())
 }
 
 impl Position {
-    fn p(&self) -> Vec2i {
+    pub fn p(&self) -> Vec2i {
         Vec2i{x: self.x, y: self.y}
     }
     
@@ -62,7 +103,7 @@ impl Position {
 impl<'a> Layout<'a> {
     //  returns manhattan distance between two shapes' squares
     // -1 if they overlap, 0 if border
-    fn distance(shape1 : &Shape, shape2 : &Shape, 
+    pub fn distance(shape1 : &Shape, shape2 : &Shape, 
         pos1: &Vec2i, pos2: &Vec2i) -> i32 
     {
         let overlap = Layout::overlap_status(shape1, shape2, pos1, pos2);
@@ -71,19 +112,21 @@ impl<'a> Layout<'a> {
            
         let dx = pos1.x - pos2.x;
         let dy = pos1.y - pos2.y;
+        if let Some(min_dist) = shape1.bits_distance(shape2, dx, dy) {
+            return min_dist - 1;
+        }
         
+        let grid = shape1.grid;
         let min_dist = shape1.squares.iter().flat_map(|sq1| {
             let x = sq1.x + dx;
             let y = sq1.y + dy;
-            shape2.squares.iter().map(move |sq2| {
-                (x - sq2.x).abs() + (y - sq2.y).abs()
-            })
+            shape2.squares.iter().map(move |sq2| grid.distance(x - sq2.x, y - sq2.y))
         }).min().unwrap();
         min_dist - 1
     }
     
     //  returns overlap status between two shapes
-    fn overlap_status(shape1 : &Shape, shape2 : &Shape, 
+    pub fn overlap_status(shape1 : &Shape, shape2 : &Shape, 
         pos1: &Vec2i, pos2: &Vec2i) -> Overlap 
     {
         if pos1.x > pos2.x + shape2.width  ||
@@ -95,6 +138,13 @@ impl<'a> Layout<'a> {
         let dx = pos1.x - pos2.x;
         let dy = pos1.y - pos2.y;
         
+        //  shift-and-AND the bit masks, if the shapes have them
+        if let Some(overlaps) = shape1.hits(shape2, dx, dy, false) {
+            if overlaps { return Overlap::Overlap; }
+            if shape1.hits(shape2, dx, dy, true) == Some(true) { return Overlap::Border; }
+            return Overlap::Disjoint;
+        }
+        
         //  test for overlapping
         for sq in &shape1.squares {
             let x = sq.x + dx;
@@ -104,7 +154,7 @@ impl<'a> Layout<'a> {
         
         //  no overlapping, test for bordering
         for sq in &shape1.squares {
-            for offs in OFFS.iter() {
+            for offs in shape1.grid.neighbors() {
                 let x = sq.x + dx + offs[0];
                 let y = sq.y + dy + offs[1];
                 if shape2.is_set(x, y) { return Overlap::Border };
@@ -113,43 +163,81 @@ impl<'a> Layout<'a> {
         Overlap::Disjoint
     }
     
+    //  lists the variations/positions of a shape that border the anchor shape
+    //  (or overlap it, as the boundary goes along its edges)
+    fn spots(anchor_shape: &Shape, anchor_pos: &Vec2i, variants: &[Shape]) -> Vec<(u16, Vec2i)> {
+        let mut res = vec![];
+        for (i, shape) in variants.iter().enumerate() {
+            for bpos in anchor_shape.boundary.iter() {
+                for cpos in shape.squares.iter() {
+                    res.push((i as u16, Vec2i{
+                        x: anchor_pos.x + bpos.x - cpos.x,
+                        y: anchor_pos.y + bpos.y - cpos.y,
+                    }));
+                }
+            }
+        }
+        res
+    }
+
     //  finds a "best fit" variation/position of a shape 
     //  (according to a fit function, minimizing its output), 
     //  so that it is bordered with the anchor shape
     fn best_fit<F>(anchor_shape: &Shape, anchor_pos: &Vec2i, 
-        variants: &Vec<Shape>, fit: F) -> (u16, Vec2i) 
+        variants: &[Shape], fit: F) -> (u16, Vec2i) 
         where F : Fn(&Vec2i, &Shape) -> f64
     {
         let mut min_d = f64::MAX;
         let mut res = (0, Vec2i{x: 0, y: 0});
-        for (i, ref shape) in variants.iter().enumerate() {
-            for bpos in anchor_shape.boundary.iter() {
-                for cpos in shape.squares.iter() {
-                    let p = Vec2i{
-                        x: anchor_pos.x + bpos.x - cpos.x,
-                        y: anchor_pos.y + bpos.y - cpos.y,
-                    };
-                    let d = fit(&p, &shape);
-                    if d < min_d {
-                        min_d = d;
-                        res = (i as u16, p)
-                    }
-                }
+        for (i, p) in Layout::spots(anchor_shape, anchor_pos, variants) {
+            let d = fit(&p, &variants[i as usize]);
+            if d < min_d {
+                min_d = d;
+                res = (i, p)
             }
         }
         res
     }
     
     //  constructor
-    pub fn new(bundle : &Bundle) -> Layout {
+    pub fn new(bundle : &Bundle) -> Layout<'_> {
         Layout {
-            bundle: bundle,
+            bundle,
             pos: (0..bundle.len()).map(|i| Position {
                 shape: i as u16, ..Position::zero()
             }).collect()
         }
     }
     
+    //  constructs the layout from a list of positions, 
+    //  checking that every shape is used exactly once, with a valid variant
+    pub fn from_positions(bundle : &Bundle, pos: Vec<Position>) 
+        -> Result<Layout<'_>, PolyfarmError> 
+    {
+        let mut used = vec![false; bundle.len()];
+        for (i, p) in pos.iter().enumerate() {
+            let shape = p.shape as usize;
+            if shape >= bundle.len() {
+                return Err(PolyfarmError::InvalidData(format!(
+                    "position #{}: no shape #{} in the bundle", i + 1, shape + 1)));
+            }
+            if used[shape] {
+                return Err(PolyfarmError::InvalidData(format!(
+                    "position #{}: shape #{} is used more than once", i + 1, shape + 1)));
+            }
+            if p.var as usize >= bundle[shape].len() {
+                return Err(PolyfarmError::InvalidData(format!(
+                    "position #{}: shape #{} has no variant #{}", i + 1, shape + 1, p.var + 1)));
+            }
+            used[shape] = true;
+        }
+        if let Some(shape) = used.iter().position(|&u| !u) {
+            return Err(PolyfarmError::InvalidData(format!(
+                "shape #{} is not used", shape + 1)));
+        }
+        Ok(Layout { bundle, pos })
+    }
+    
     //  shuffles shape order
     pub fn shuffle<T: Rng>(&mut self, rng : &mut T) {
         for i in 0..self.bundle.len() {
@@ -164,6 +252,11 @@ impl<'a> Layout<'a> {
     pub fn shape_by_pos(&self, pos: &Position) -> &Shape {
         &self.bundle[pos.shape as usize][pos.var as usize]
     }
+
+    //  the grid the shapes are on
+    pub fn grid(&self) -> Grid {
+        self.bundle[0][0].grid
+    }
        
     //  lays out the chain of shapes along a circle with given radius,
     //  picking positions/variants such that neighbor shapes bound each other    
@@ -214,6 +307,25 @@ impl<'a> Layout<'a> {
         }
     }
     
+    //  orders the shapes along the wall, by the angle of their centers 
+    //  around the center of the layout (the same way arrange_circle goes)
+    pub fn sort_by_angle(&mut self) {
+        let grid = self.grid();
+        let centers: Vec<(f64, f64, f64)> = self.pos.iter().map(|p| {
+            let sh = self.shape_by_pos(p);
+            let (x, y) = grid.point(sh.squares.iter().map(|s| (p.x + s.x) as f64).sum::<f64>(),
+                sh.squares.iter().map(|s| (p.y + s.y) as f64).sum::<f64>());
+            (x, y, sh.squares.len() as f64)
+        }).collect();
+        let n = centers.iter().map(|c| c.2).sum::<f64>();
+        let cx = centers.iter().map(|c| c.0).sum::<f64>()/n;
+        let cy = centers.iter().map(|c| c.1).sum::<f64>()/n;
+        let mut order: Vec<(f64, Position)> = centers.iter().zip(self.pos.drain(..))
+            .map(|(c, p)| ((c.1/c.2 - cy).atan2(c.0/c.2 - cx), p)).collect();
+        order.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        self.pos = order.into_iter().map(|(_, p)| p).collect();
+    }
+    
     //  returns layout bounds, (LeftTop, RightBottom)
     pub fn bounds(&self) -> (Vec2i, Vec2i) {
         let start = (Vec2i{x:i32::MAX, y:i32::MAX}, Vec2i{x:i32::MIN, y:i32::MIN});
@@ -235,7 +347,9 @@ impl<'a> Layout<'a> {
         ).collect();
     }
     
-    fn flood_fill<F>(&self, mut hit_fn: F) -> Option<usize> 
+    //  fills the area enclosed by the shapes, calling hit_fn for every cell, 
+    //  returns the number of cells or None if the fill leaks outside the bounds
+    pub fn flood_fill<F>(&self, mut hit_fn: F) -> Option<usize> 
         where F : FnMut(i32, i32) 
     {
         let (lt, rb) = self.bounds();
@@ -245,7 +359,7 @@ impl<'a> Layout<'a> {
         // create the mask
         let mut mask = vec![false; (w*h) as usize];
         for p in &self.pos {
-            let shape = self.shape_by_pos(&p);
+            let shape = self.shape_by_pos(p);
             for sq in &shape.squares {
                 let x = p.x + sq.x - lt.x;
                 let y = p.y + sq.y - lt.y;
@@ -253,11 +367,20 @@ impl<'a> Layout<'a> {
             }
         }
         
-        //  compute the starting point
-        let mut sx = w/2;
-        let mut sy = h/2;
+        //  compute the starting point (on the hex grid, the middle of the cells, 
+        //  as the middle of the box may well be off the layout's corner)
+        let offsets = self.grid().fill_neighbors();
+        let (mut sx, mut sy) = match self.grid() {
+            Grid::Square => (w/2, h/2),
+            Grid::Hex => {
+                let cells = self.cells();
+                let n = cells.len() as i32;
+                let (x, y) = cells.iter().fold((0, 0), |(x, y), c| (x + c.x - lt.x, y + c.y - lt.y));
+                ((x + n/2)/n, (y + n/2)/n)
+            }
+        };
         if mask[(sx + sy*w) as usize] {
-            for offs in COFFS.iter() {
+            for offs in offsets {
                 let cx = sx + offs[0];
                 let cy = sy + offs[1];
                 if !mask[(cx + cy*w) as usize] {
@@ -273,14 +396,10 @@ impl<'a> Layout<'a> {
         mask[(sx + sy*w) as usize] = true;
         let mut nvisited = 0;
             
-        loop {
-            let c = match cellq.pop() {
-                Some(p) => p,
-                None => break
-            };
+        while let Some(c) = cellq.pop() {
             hit_fn(c.x + lt.x, c.y + lt.y);
             nvisited += 1;
-            for offs in COFFS.iter() {
+            for offs in offsets {
                 let cx = c.x + offs[0];
                 let cy = c.y + offs[1];
                 if cx < 0 || cy < 0 || cx >= w || cy >= h { return None; }
@@ -303,8 +422,8 @@ impl<'a> Layout<'a> {
                 //  non-closed area (a brezel)
                 let dist = self.pos.iter().enumerate().map(|(i, p)| {
                     let sh = self.shape_by_pos(p);
-                    let p1 = &self.pos[(i + 1)%nshapes as usize];
-                    let sh1 = self.shape_by_pos(&p1);
+                    let p1 = &self.pos[(i + 1)%nshapes];
+                    let sh1 = self.shape_by_pos(p1);
                     Layout::distance(sh, sh1, &p.p(), &p1.p()).abs() as f64
                 }).fold(0.0, |sum, i| sum + i);
                 -dist
@@ -312,38 +431,266 @@ impl<'a> Layout<'a> {
         }
     }
     
+    //  order-preserving crossover on the (cyclic) chain: keeps the section of the given length,
+    //  starting at the given index, as it is, and follows it with the rest of the shapes 
+    //  in the order they go in the other layout (starting after the same index), 
+    //  moved over to join the section. Then every shape of the rest gets fitted to the one 
+    //  before it, clear of the shapes placed so far, and the last one to the start of the section 
+    //  too (which a single shape left over may be unable to reach)
+    pub fn crossover(&self, other: &Layout<'a>, start: usize, len: usize) -> Layout<'a> {
+        let n = self.pos.len();
+        let pos: Vec<Position> = (0..len).map(|k| self.pos[(start + k)%n].clone()).collect();
+        let mut used = vec![false; self.bundle.len()];
+        for p in &pos { used[p.shape as usize] = true; }
+        let rest: Vec<Position> = (0..n).map(|k| &other.pos[(start + len + k)%n])
+            .filter(|p| !used[p.shape as usize]).cloned().collect();
+        let mut res = Layout { bundle: self.bundle, pos };
+        if rest.is_empty() || len == 0 { 
+            return res; 
+        }
+        
+        //  move the rest next to the end of the section, as close as possible to where it was
+        let (_, p) = res.fit_next(&res.pos[len - 1], &rest[0], None, &[]);
+        let (dx, dy) = (p.x - rest[0].x, p.y - rest[0].y);
+        res.pos.extend(rest.iter().map(|r| Position { x: r.x + dx, y: r.y + dy, ..r.clone() }));
+        
+        //  and re-fit it shape by shape
+        for k in len..n {
+            let next = if k == n - 1 { Some(&res.pos[0]) } else { None };
+            let (var, p) = res.fit_next(&res.pos[k - 1], &res.pos[k], next, &res.pos[..(k - 1)]);
+            res.pos[k] = Position { x: p.x, y: p.y, var, ..res.pos[k].clone() };
+        }
+        
+        //  when the last shape can't reach the start of the section, the one before it 
+        //  tries its other spots, nearest first, until the last one fits in between
+        if n - len > 1 && !res.borders(n - 1, 0) {
+            let (prev, cur) = (&res.pos[n - 3], &res.pos[n - 2]);
+            let prev_shape = res.shape_by_pos(prev);
+            let variants = &res.bundle[cur.shape as usize];
+            let mut spots: Vec<(i32, u16, Vec2i)> = Layout::spots(prev_shape, &prev.p(), variants)
+                .into_iter().filter(|&(var, p)| {
+                    let shape = &variants[var as usize];
+                    Layout::distance(shape, prev_shape, &p, &prev.p()) == 0 && res.pos[..(n - 3)].iter()
+                        .all(|a| Layout::distance(shape, res.shape_by_pos(a), &p, &a.p()) >= 0)
+                })
+                .map(|(var, p)| (variants[var as usize].grid.distance(p.x - cur.x, p.y - cur.y), var, p))
+                .collect();
+            spots.sort_by_key(|s| s.0);
+            let mut child = res.clone();
+            for (_, var, p) in spots {
+                child.pos[n - 2] = Position { x: p.x, y: p.y, var, ..res.pos[n - 2].clone() };
+                let (var, p) = child.fit_next(&child.pos[n - 2], &child.pos[n - 1], 
+                    Some(&child.pos[0]), &child.pos[..(n - 2)]);
+                child.pos[n - 1] = Position { x: p.x, y: p.y, var, ..res.pos[n - 1].clone() };
+                if child.borders(n - 1, 0) && child.borders(n - 2, n - 1) &&
+                    (1..(n - 2)).all(|k| !child.overlaps(k, n - 1))
+                {
+                    return child;
+                }
+            }
+        }
+        res
+    }
+    
+    //  checks that the two shapes touch without overlapping
+    fn borders(&self, i: usize, j: usize) -> bool {
+        Layout::distance(self.shape_by_pos(&self.pos[i]), self.shape_by_pos(&self.pos[j]), 
+            &self.pos[i].p(), &self.pos[j].p()) == 0
+    }
+    
+    fn overlaps(&self, i: usize, j: usize) -> bool {
+        Layout::distance(self.shape_by_pos(&self.pos[i]), self.shape_by_pos(&self.pos[j]), 
+            &self.pos[i].p(), &self.pos[j].p()) < 0
+    }
+    
+    //  returns the position of the shape turned by a rotation step (clockwise or not) 
+    //  around its center, unchanged if the bundle has no such variant
+    pub fn rotate_in_place(&self, i: usize, clockwise: bool) -> Position {
+        let p = &self.pos[i];
+        let shape = self.shape_by_pos(p);
+        let turned = shape.turned(if clockwise { 1 } else { shape.grid.rotations() - 1 });
+        match self.bundle[p.shape as usize].iter().position(|v| *v == turned) {
+            Some(var) => Position { 
+                x: p.x + (shape.width - turned.width)/2, 
+                y: p.y + (shape.height - turned.height)/2, 
+                var: var as u16, ..p.clone() 
+            },
+            None => p.clone()
+        }
+    }
+    
+    //  returns the variant/position of the shape that borders both of its neighbors on the chain,
+    //  staying as close as possible to where it is
+    pub fn refit(&self, i: usize) -> Position {
+        let n = self.pos.len();
+        if n < 2 { return self.pos[i].clone(); }
+        let (prev, next) = (&self.pos[(i + n - 1)%n], &self.pos[(i + 1)%n]);
+        let (var, p) = self.fit_next(prev, &self.pos[i], Some(next), &[]);
+        Position { x: p.x, y: p.y, var, ..self.pos[i].clone() }
+    }
+    
+    //  finds the variant/position of the shape that borders the previous one 
+    //  (and the next one, if given), staying as close as possible to where it is
+    //  and off the other given shapes
+    fn fit_next(&self, prev: &Position, pos: &Position, next: Option<&Position>, 
+        avoid: &[Position]) -> (u16, Vec2i) 
+    {
+        let prev_shape = self.shape_by_pos(prev);
+        let variants = &self.bundle[pos.shape as usize];
+        Layout::best_fit(prev_shape, &prev.p(), variants, |p, shape| {
+            let d = Layout::distance(shape, prev_shape, p, &prev.p());
+            if d != 0 { return 3.0*MAX_DIST }
+            if avoid.iter().any(|a| Layout::distance(shape, self.shape_by_pos(a), p, &a.p()) < 0) {
+                return 2.0*MAX_DIST
+            }
+            let offs = shape.grid.distance(p.x - pos.x, p.y - pos.y) as f64;
+            if let Some(next) = next {
+                let d1 = Layout::distance(shape, self.shape_by_pos(next), p, &next.p());
+                if d1 != 0 { return MAX_DIST + (d1.abs() as f64) }
+            }
+            offs
+        })
+    }
+    
     pub fn extract_core(&self) -> Option<(Shape, Vec2i)> {
         let mut squares = vec![];
         let (mut cx, mut cy) = (i32::MAX, i32::MAX);
         let num_visited = self.flood_fill(|x, y| {
             cx = cmp::min(cx, x);
             cy = cmp::min(cy, y);
-            squares.push(Vec2i{x: x, y: y});
+            squares.push(Vec2i{x, y});
         });
         
         match num_visited {
             Some(_) => {
                 squares = squares.iter().map(|p| Vec2i{x: p.x - cx, y: p.y - cy}).collect();
-                Some((Shape::new(squares), Vec2i{x: cx, y: cy}))            
+                Some((Shape::with_grid(squares, self.grid()), Vec2i{x: cx, y: cy}))            
             },
             None => None
         }
     }
+
+    //  squares covered by the shapes, sorted
+    pub fn cells(&self) -> Vec<Vec2i> {
+        let mut res: Vec<Vec2i> = self.pos.iter().flat_map(|p| {
+            self.shape_by_pos(p).squares.iter().map(move |s| Vec2i{x: p.x + s.x, y: p.y + s.y})
+        }).collect();
+        res.sort();
+        res
+    }
+
+    //  covered squares in the canonical form, the same for the layouts that only differ
+    //  by translation, rotation, reflection or where the chain starts: the smallest 
+    //  of the transformed lists (8 of them, 12 on the hex grid), each moved to the origin and sorted
+    pub fn canonical_cells(&self) -> Vec<Vec2i> {
+        let cells = self.cells();
+        let grid = self.grid();
+        let n = grid.rotations();
+        (0..2*n).map(|t| {
+            let mut res: Vec<Vec2i> = cells.iter().map(|&c| {
+                let c = if t >= n { grid.mirror(c) } else { c };
+                (0..t%n).fold(c, |c, _| grid.turn(c))
+            }).collect();
+            let min_x = res.iter().map(|c| c.x).min().unwrap_or(0);
+            let min_y = res.iter().map(|c| c.y).min().unwrap_or(0);
+            for c in &mut res {
+                *c = Vec2i{x: c.x - min_x, y: c.y - min_y};
+            }
+            res.sort();
+            res
+        }).min().unwrap()
+    }
+
+    //  hash of the canonical form of the covered squares
+    pub fn canonical_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.canonical_cells().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    //  number of squares in only one of the two sorted lists
+    pub fn cell_distance(cells1: &[Vec2i], cells2: &[Vec2i]) -> usize {
+        let (mut i, mut j, mut common) = (0, 0, 0);
+        while i < cells1.len() && j < cells2.len() {
+            match cells1[i].cmp(&cells2[j]) {
+                cmp::Ordering::Less => i += 1,
+                cmp::Ordering::Greater => j += 1,
+                cmp::Ordering::Equal => {
+                    common += 1;
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        cells1.len() + cells2.len() - 2*common
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use super::super::math::*;
-    use super::super::shape::Shape;
+    
+    use super::super::shape::{Shape, Transform, Transforms, Rotation, OFFS};
+    use rand::{SeedableRng, StdRng};
+    use polyomino::verify::verify;
+
+    #[test]
+    fn test_parse_bundle() {
+        let bundle = parse_bundle("OO\r\nOO\r\n\r\n\r\nOOOO\r\n\r\n", true, true).unwrap();
+        assert_eq!(2, bundle.len());
+        assert_eq!(1, bundle[0].len());
+        assert_eq!(2, bundle[1].len());
+
+        match parse_bundle("OO\n\nO\tO\n", true, true) {
+            Err(PolyfarmError::Parse { shape: 1, line: 3, .. }) => (),
+            _ => panic!("tabs should not parse")
+        }
+        match parse_bundle(" \n\n", true, true) {
+            Err(PolyfarmError::EmptyBundle) => (),
+            _ => panic!("empty bundle should not parse")
+        }
+        match parse_bundle("polyfarm-shapes 2\n# nothing here\n", true, true) {
+            Err(PolyfarmError::EmptyBundle) => (),
+            _ => panic!("empty bundle should not parse")
+        }
+    }
+
+    #[test]
+    fn test_parse_bundle_versioned() {
+        let input = "polyfarm-shapes 2\n[L] count=2 transforms=one-sided\nOOO\nO\n\
+            [S] transforms=fixed color=0a0\n.OO\nOO\n[T]\nOOO\n.O.\n";
+        let bundle = parse_bundle(input, true, true).unwrap();
+        assert_eq!(4, bundle.len());
+        //  the copies, with the rotations only
+        assert_eq!((4, 4), (bundle[0].len(), bundle[1].len()));
+        assert!(bundle[0].iter().all(|s| !s.transform.mirrored));
+        assert!(bundle[1].iter().all(|s| s.name.as_deref() == Some("L")));
+        assert_eq!(1, bundle[2].len());
+        assert_eq!(Some("0a0"), bundle[2][0].color.as_deref());
+        assert_eq!(4, bundle[3].len());
+        assert!(check_bundle(&bundle).is_ok());
+        //  the global flags still apply
+        let bundle = parse_bundle(input, false, false).unwrap();
+        assert!(bundle.iter().all(|v| v.len() == 1));
+        //  a fixed shape can't be turned
+        let mut bundle = parse_bundle(input, true, true).unwrap();
+        let mut turned = bundle[2][0].rotated(Rotation::CW90);
+        turned.transforms = Transforms::Fixed;
+        bundle[2].push(turned);
+        assert!(check_bundle(&bundle).is_err());
+        //  nor can a shape go without variants
+        let mut bundle = parse_bundle(input, true, true).unwrap();
+        bundle.insert(0, vec![]);
+        assert!(check_bundle(&bundle).is_err());
+    }
 
     #[test]
     fn test_overlap_status() {
         let shape1 = "   \n O\n OOO \n O\n";
-        let shape1 = Shape::parse(shape1);
+        let shape1 = Shape::parse(shape1).unwrap();
 
         let shape2 = "   O\n OOO \n O\n";
-        let shape2 = Shape::parse(shape2);
+        let shape2 = Shape::parse(shape2).unwrap();
         
         assert_eq!(Overlap::Overlap, Layout::overlap_status(&shape1, &shape2, 
             &Vec2i{x: 0, y: 0}, &Vec2i{x: 0, y: 0}));
@@ -361,13 +708,42 @@ mod tests {
             &Vec2i{x: -2, y: 0}, &Vec2i{x: 0, y: 2}));
     }
     
+    #[test]
+    fn test_bits_match_squares() {
+        //  every pair of tetromino variants at every nearby relative position
+        let bundle = parse_bundle(include_str!("../../data/tetromino.txt"), true, true).unwrap();
+        let shapes: Vec<&Shape> = bundle.iter().flat_map(|v| v.iter()).collect();
+        let origin = Vec2i{x: 0, y: 0};
+        for &shape1 in &shapes {
+            for &shape2 in &shapes {
+                for y in -6..7 {
+                    for x in -6..7 {
+                        let (dx, dy) = (x, y);
+                        let overlaps = shape1.squares.iter()
+                            .any(|sq| shape2.is_set(sq.x + dx, sq.y + dy));
+                        let borders = shape1.squares.iter().any(|sq| OFFS.iter()
+                            .any(|o| shape2.is_set(sq.x + dx + o[0], sq.y + dy + o[1])));
+                        let dist = shape1.squares.iter().flat_map(|s1| shape2.squares.iter()
+                            .map(move |s2| (s1.x + dx - s2.x).abs() + (s1.y + dy - s2.y).abs()))
+                            .min().unwrap();
+                        assert_eq!(Some(overlaps), shape1.hits(shape2, dx, dy, false));
+                        assert_eq!(Some(borders), shape1.hits(shape2, dx, dy, true));
+                        assert_eq!(Some(dist), shape1.bits_distance(shape2, dx, dy));
+                        let d = Layout::distance(shape1, shape2, &Vec2i{x, y}, &origin);
+                        assert_eq!(if overlaps { -1 } else if borders { 0 } else { dist - 1 }, d);
+                    }
+                }
+            }
+        }
+    }
+    
     #[test]
     fn test_shape_dist() {
         let shape2 = "   O\n OOO \n O\n";
-        let shape2 = Shape::parse(shape2);
+        let shape2 = Shape::parse(shape2).unwrap();
         
         let shape3 = "OOOO\n   O\n";
-        let shape3 = Shape::parse(shape3);  
+        let shape3 = Shape::parse(shape3).unwrap();  
         
         assert_eq!(-1, Layout::distance(&shape2, &shape3,
             &Vec2i{x: 0, y: 0}, &Vec2i{x: 0, y: 0}));     
@@ -385,10 +761,10 @@ mod tests {
     #[test]
     fn test_shape_dist2() {
         let shape1 = "OOOO\nO\n";
-        let shape1 = Shape::parse(shape1);
+        let shape1 = Shape::parse(shape1).unwrap();
         
         let shape2 = "O\nO\nO\nO\nO\n";
-        let shape2 = Shape::parse(shape2);
+        let shape2 = Shape::parse(shape2).unwrap();
         
         assert_eq!(0, Layout::distance(&shape1, &shape2,
             &Vec2i{x: 0, y: 1}, &Vec2i{x: 3, y: 2}));     
@@ -400,17 +776,17 @@ mod tests {
     #[test]
     fn test_best_fit() {
         let shape1 = "OOOO\nO\n";
-        let shape1 = Shape::parse(shape1);
+        let shape1 = Shape::parse(shape1).unwrap();
         
         let shape2 = "OOOOO\n";
-        let shape2 = Shape::parse(shape2);
+        let shape2 = Shape::parse(shape2).unwrap();
         
         let variants = shape2.variants(true, true);
         
         let pos1 = Vec2i{ x: 0, y: 1 };
         let res = Layout::best_fit(&shape1, &pos1, 
             &variants, |pos, shape| {
-                let d = Layout::distance(&shape1, &shape, &pos1, &pos);
+                let d = Layout::distance(&shape1, shape, &pos1, pos);
                 if d != 0 {return 1000.0}
                 (-(pos.x + shape.width)) as f64
             }); 
@@ -418,5 +794,128 @@ mod tests {
         assert_eq!(2, variants.len());
         assert_eq!((1, Vec2i{ x: 4, y: 1 }), res);
     }
-    
+
+    #[test]
+    fn test_crossover() {
+        let bundle = parse_bundle(include_str!("../../data/tetromino.txt"), true, true).unwrap();
+        let seed: &[_] = &[1, 2, 3];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let mut layout1 = Layout::new(&bundle);
+        layout1.shuffle(&mut rng);
+        layout1.arrange_circle(3.0);
+        let mut layout2 = Layout::new(&bundle);
+        layout2.shuffle(&mut rng);
+        layout2.arrange_circle(3.0);
+        
+        let child = layout1.crossover(&layout2, 3, 2);
+        //  the section stays where it was, the rest keeps the order of the other layout
+        assert!(Layout::from_positions(&bundle, child.pos.clone()).is_ok());
+        assert_eq!(&layout1.pos[3..5], &child.pos[0..2]);
+        let order: Vec<u16> = (0..5).map(|k| layout2.pos[k].shape)
+            .filter(|&s| s != layout1.pos[3].shape && s != layout1.pos[4].shape).collect();
+        assert_eq!(order, child.pos[2..].iter().map(|p| p.shape).collect::<Vec<_>>());
+        //  the seams are closed
+        let dist = |i: usize, j: usize| Layout::distance(child.shape_by_pos(&child.pos[i]),
+            child.shape_by_pos(&child.pos[j]), &child.pos[i].p(), &child.pos[j].p());
+        for i in 0..5 {
+            assert_eq!(0, dist(i, (i + 1)%5));
+        }
+        assert!(verify(&child).overlaps.is_empty());
+        
+        //  the whole chain
+        assert!(layout1.crossover(&layout2, 1, 5) == Layout { 
+            bundle: &bundle, pos: layout1.pos[1..].iter().chain(&layout1.pos[..1]).cloned().collect() 
+        });
+    }
+
+    #[test]
+    fn test_rotate_refit() {
+        let bundle = parse_bundle("OOOO\n\nOO\nOO\n\nOOO\nO\n", true, true).unwrap();
+        let mut layout = Layout::new(&bundle);
+        layout.pos[0] = Position { x: 0, y: 0, shape: 0, var: 0 };
+        let turned = layout.rotate_in_place(0, true);
+        let (shape, turned_shape) = (layout.shape_by_pos(&layout.pos[0]), layout.shape_by_pos(&turned));
+        assert_eq!((shape.width, shape.height), (turned_shape.height, turned_shape.width));
+        //  the center stays (up to rounding)
+        assert!((2*turned.x + turned_shape.width - shape.width).abs() <= 1);
+        assert!((2*turned.y + turned_shape.height - shape.height).abs() <= 1);
+        layout.pos[0] = turned;
+        assert_eq!(Position { x: 0, y: 0, shape: 0, var: 0 }, layout.rotate_in_place(0, false));
+        //  the square has nowhere to turn
+        assert_eq!(layout.pos[1], layout.rotate_in_place(1, true));
+        
+        layout.pos[1] = Position { x: 7, y: 9, ..layout.pos[1].clone() };
+        let fitted = layout.refit(1);
+        let dist = |i: usize| Layout::distance(layout.shape_by_pos(&fitted), 
+            layout.shape_by_pos(&layout.pos[i]), &fitted.p(), &layout.pos[i].p());
+        assert_eq!((0, 0), (dist(0), dist(2)));
+    }
+
+    #[test]
+    fn test_canonical_cells() {
+        let bundle = parse_bundle("OOO\nO\n\nOO\nOO\n", true, true).unwrap();
+        let var = |t: Transform| bundle[0].iter().position(|s| s.transform == t).unwrap() as u16;
+        let mut layout = Layout::new(&bundle);
+        layout.pos[0] = Position { x: 0, y: 0, shape: 0, var: var(Transform::identity()) };
+        layout.pos[1] = Position { x: 3, y: 0, shape: 1, var: 0 };
+        let cells = layout.canonical_cells();
+        assert_eq!(8, cells.len());
+        assert_eq!(Vec2i{x: 0, y: 0}, cells[0]);
+        
+        //  mirrored, moved, and with the chain starting elsewhere
+        let mirrored = var(Transform { mirrored: true, rotation: 0 });
+        let mut other = Layout::new(&bundle);
+        other.pos[0] = Position { x: 10, y: 5, shape: 1, var: 0 };
+        other.pos[1] = Position { x: 12, y: 5, shape: 0, var: mirrored };
+        assert_eq!(cells, other.canonical_cells());
+        assert_eq!(layout.canonical_hash(), other.canonical_hash());
+        //  and turned
+        for var in 0..bundle[0].len() {
+            let mut turned = layout.clone();
+            turned.pos[0].var = var as u16;
+            turned.pos[1] = turned.refit(1);
+            assert_eq!(cells == turned.canonical_cells(), 
+                layout.canonical_hash() == turned.canonical_hash());
+        }
+
+        //  the square moved a step away
+        let before = layout.cells();
+        layout.pos[1].x = 4;
+        assert!(cells != layout.canonical_cells());
+        assert_eq!(4, Layout::cell_distance(&before, &layout.cells()));
+        assert_eq!(0, Layout::cell_distance(&before, &before));
+        assert_eq!(5, Layout::cell_distance(&before[..5], &before[2..]));
+    }
+
+    #[test]
+    fn test_hex_ring() {
+        //  two arcs of three hexagons around a single one
+        let bundle = parse_bundle("polyfarm-shapes 2 grid=hex\n[C] count=2\n O\n  O\n O\n",
+            true, true).unwrap();
+        assert_eq!(Grid::Hex, bundle[0][0].grid);
+        assert_eq!(6, bundle[0].len());
+        let var = |squares: &[(i32, i32)]| {
+            let squares: Vec<Vec2i> = squares.iter().map(|&(x, y)| Vec2i{x, y}).collect();
+            bundle[0].iter().position(|s| s.squares == squares).unwrap() as u16
+        };
+        let mut layout = Layout::new(&bundle);
+        layout.pos[0] = Position { x: 0, y: -1, shape: 0, var: var(&[(0, 2), (1, 0), (1, 1)]) };
+        layout.pos[1] = Position { x: -1, y: -1, shape: 1, var: var(&[(0, 1), (0, 2), (1, 0)]) };
+        assert_eq!(1.0, layout.score());
+        let (core, pos) = layout.extract_core().unwrap();
+        assert_eq!((vec![Vec2i{x: 0, y: 0}], Vec2i{x: 0, y: 0}), (core.squares, pos));
+        //  the arcs touch, and don't overlap
+        let (s0, s1) = (layout.shape_by_pos(&layout.pos[0]), layout.shape_by_pos(&layout.pos[1]));
+        assert_eq!(Overlap::Border,
+            Layout::overlap_status(s0, s1, &layout.pos[0].p(), &layout.pos[1].p()));
+        //  the same ring, split a sixth of the turn further
+        let mut turned = Layout::new(&bundle);
+        turned.pos[0] = Position { x: 0, y: -1, shape: 0, var: var(&[(0, 0), (1, 0), (1, 1)]) };
+        turned.pos[1] = Position { x: -1, y: 0, shape: 1, var: var(&[(0, 0), (0, 1), (1, 1)]) };
+        assert_eq!(1.0, turned.score());
+        assert_eq!(layout.canonical_cells(), turned.canonical_cells());
+        //  a gap lets the area out
+        layout.pos[1].x -= 1;
+        assert!(layout.score() < 0.0);
+    }
 }
@@ -7,7 +7,7 @@ use num::Num;
 pub type Vec2i = Vec2<i32>;
 pub type Vec2f = Vec2<f64>;
 
-#[derive(Copy, Clone, PartialEq, PartialOrd, Ord, Eq, Debug)]
+#[derive(Copy, Clone, PartialEq, PartialOrd, Ord, Eq, Hash, Debug, Serialize, Deserialize)]
 pub struct Vec2<T> {
     pub x: T,
     pub y: T,
@@ -16,7 +16,7 @@ pub struct Vec2<T> {
 // implementation
 impl<T: Num> Vec2<T> {
     pub fn new(x: T, y: T) -> Vec2<T> {
-        Vec2 { x: x, y: y }
+        Vec2 { x, y }
     } 
 }
 
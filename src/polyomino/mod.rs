@@ -1,5 +1,19 @@
 pub mod layout;
 pub mod shape;
 pub mod farm;
-
-mod math;
+pub mod math;
+pub mod error;
+pub mod checkpoint;
+pub mod export;
+pub mod verify;
+pub mod sketch;
+pub mod exact;
+pub mod occupancy;
+pub mod mutation;
+pub mod strategy;
+pub mod genetic;
+pub mod anneal;
+pub mod islands;
+pub mod shapefile;
+pub mod enumerate;
+pub mod grid;
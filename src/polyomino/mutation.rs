@@ -0,0 +1,474 @@
+// ------------------------------------------------------------------------------------------------
+// mutation.rs
+// ------------------------------------------------------------------------------------------------
+use std::f64;
+use rand::{Rng};
+
+use polyomino::layout::{Layout, Position};
+use polyomino::occupancy::Occupancy;
+use polyomino::error::PolyfarmError;
+
+//  Kinds of the random flips applied to the layouts
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mutation {
+    Variant,    //  change the variants of two shapes
+    Offset,     //  move a section of the chain by a square
+    Swap,       //  swap two shapes
+    Reverse,    //  reverse the order of a section of the chain
+    Rotate,     //  turn a shape around its center
+    Refit,      //  re-fit a shape to border its neighbors on the chain
+}
+
+//  share of the probability spread evenly between the adapted choices,
+//  so that none of them gets abandoned
+const MIN_SHARE: f64 = 0.2;
+//  how fast the adapted success rates follow the recent ones
+const ADAPTATION_RATE: f64 = 0.3;
+
+//  all the mutations, in the order they are picked by
+pub const MUTATIONS: [Mutation; 6] = [
+    Mutation::Variant, Mutation::Offset, Mutation::Swap,
+    Mutation::Reverse, Mutation::Rotate, Mutation::Refit
+];
+
+impl Mutation {
+    pub fn name(self) -> &'static str {
+        match self {
+            Mutation::Variant => "variant",
+            Mutation::Offset => "offset",
+            Mutation::Swap => "swap",
+            Mutation::Reverse => "reverse",
+            Mutation::Rotate => "rotate",
+            Mutation::Refit => "refit",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Mutation> {
+        MUTATIONS.iter().find(|m| m.name() == name).cloned()
+    }
+}
+
+//  Which mutations to use, how often, and how many flips make an attempt
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MutationConfig {
+    pub min_flips: u32,     //  flips per mutation attempt, at least
+    pub max_flips: u32,     //  and at most
+    pub variant: u32,       //  relative weights of the mutations
+    pub offset: u32,
+    pub swap: u32,
+    pub reverse: u32,
+    pub rotate: u32,
+    pub refit: u32,
+    pub adaptive: bool,     //  adapt the probabilities of the mutations and flip counts 
+                            //  to how they do, starting from the weights
+}
+
+impl Default for MutationConfig {
+    fn default() -> MutationConfig {
+        MutationConfig { min_flips: 2, max_flips: 4,
+            variant: 1, offset: 1, swap: 1, reverse: 0, rotate: 0, refit: 0, adaptive: false }
+    }
+}
+
+impl MutationConfig {
+    pub fn weight(&self, m: Mutation) -> u32 {
+        match m {
+            Mutation::Variant => self.variant,
+            Mutation::Offset => self.offset,
+            Mutation::Swap => self.swap,
+            Mutation::Reverse => self.reverse,
+            Mutation::Rotate => self.rotate,
+            Mutation::Refit => self.refit,
+        }
+    }
+
+    pub fn weight_mut(&mut self, m: Mutation) -> &mut u32 {
+        match m {
+            Mutation::Variant => &mut self.variant,
+            Mutation::Offset => &mut self.offset,
+            Mutation::Swap => &mut self.swap,
+            Mutation::Reverse => &mut self.reverse,
+            Mutation::Rotate => &mut self.rotate,
+            Mutation::Refit => &mut self.refit,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), PolyfarmError> {
+        if self.min_flips == 0 || self.min_flips > self.max_flips {
+            return Err(PolyfarmError::Config(format!(
+                "flips per mutation must be positive and increasing, got {} to {}",
+                self.min_flips, self.max_flips)));
+        }
+        if MUTATIONS.iter().all(|&m| self.weight(m) == 0) {
+            return Err(PolyfarmError::Config(String::from(
+                "at least one mutation must have a positive weight")));
+        }
+        Ok(())
+    }
+
+    //  picks a mutation with the probability proportional to its weight
+    fn pick<R: Rng>(&self, rng: &mut R) -> Mutation {
+        let total = MUTATIONS.iter().map(|&m| self.weight(m)).sum();
+        let mut r = rng.gen_range(0, total);
+        for &m in MUTATIONS.iter() {
+            if r < self.weight(m) { return m; }
+            r -= self.weight(m);
+        }
+        unreachable!()
+    }
+}
+
+//  How a mutation did during an iteration
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MutationStats {
+    pub name: String,
+    pub attempts: u64,      //  mutation attempts it took part in
+    pub successes: u64,     //  how many of them scored above the layout they were made on
+}
+
+//  adds the counts up into the totals, by the mutation name
+pub fn add_stats(totals: &mut Vec<MutationStats>, stats: &[MutationStats]) {
+    for m in stats {
+        match totals.iter_mut().find(|t| t.name == m.name) {
+            Some(t) => {
+                t.attempts += m.attempts;
+                t.successes += m.successes;
+            },
+            None => totals.push(m.clone())
+        }
+    }
+}
+
+//  Per-mutation (and per flip count) counts of the attempts and the successes
+#[derive(Clone, Default)]
+pub struct MutationCounts {
+    attempts: [u64; 6],
+    successes: [u64; 6],
+    flips: Vec<(u64, u64)>,     //  attempts and successes by the number of flips
+}
+
+impl MutationCounts {
+    //  counts an attempt made of the given (bit set of) mutations and number of flips
+    pub fn record(&mut self, used: u32, num_flips: usize, success: bool) {
+        for i in 0..MUTATIONS.len() {
+            if used & (1 << i) != 0 {
+                self.attempts[i] += 1;
+                if success { self.successes[i] += 1; }
+            }
+        }
+        if self.flips.len() <= num_flips {
+            self.flips.resize(num_flips + 1, (0, 0));
+        }
+        self.flips[num_flips].0 += 1;
+        if success { self.flips[num_flips].1 += 1; }
+    }
+
+    pub fn add(&mut self, other: &MutationCounts) {
+        for i in 0..MUTATIONS.len() {
+            self.attempts[i] += other.attempts[i];
+            self.successes[i] += other.successes[i];
+        }
+        if self.flips.len() < other.flips.len() {
+            self.flips.resize(other.flips.len(), (0, 0));
+        }
+        for (f, o) in self.flips.iter_mut().zip(&other.flips) {
+            f.0 += o.0;
+            f.1 += o.1;
+        }
+    }
+
+    //  the statistics of the mutations in use
+    pub fn stats(&self, config: &MutationConfig) -> Vec<MutationStats> {
+        MUTATIONS.iter().enumerate().filter(|&(_, &m)| config.weight(m) > 0).map(|(i, m)| {
+            MutationStats { name: String::from(m.name()),
+                attempts: self.attempts[i], successes: self.successes[i] }
+        }).collect()
+    }
+}
+
+//  Probabilities of the mutations and of the flip counts in the adaptive mode.
+//  They follow the success rates (probability matching): the estimated rates start from 
+//  the ones first seen, every later iteration moves them towards the ones just seen, 
+//  and the probabilities are shared in proportion to them, except for a small part spread evenly
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MutationRates {
+    pub mutations: Vec<f64>,        //  probabilities of the mutations, in the MUTATIONS order
+    pub flips: Vec<f64>,            //  probabilities of the flip counts, from the minimum one up
+    pub mutation_success: Vec<Option<f64>>, //  estimated success rates of the mutations, once tried
+    pub flip_success: Vec<Option<f64>>,     //  and of the flip counts
+}
+
+impl MutationRates {
+    //  constructor, starting from the weights and the even flip counts
+    pub fn new(config: &MutationConfig) -> MutationRates {
+        let nflips = (config.max_flips - config.min_flips + 1) as usize;
+        let mut res = MutationRates {
+            mutations: vec![],
+            flips: vec![],
+            mutation_success: vec![None; MUTATIONS.len()],
+            flip_success: vec![None; nflips],
+        };
+        res.share(config);
+        res
+    }
+
+    //  the rates to start the search with: none unless in the adaptive mode,
+    //  otherwise the last ones (when resuming) or the initial ones
+    pub fn start(config: &MutationConfig, last: Option<&MutationRates>) -> Option<MutationRates> {
+        if !config.adaptive { return None; }
+        Some(last.cloned().unwrap_or_else(|| MutationRates::new(config)))
+    }
+
+    //  moves the success rates towards the ones of the last iteration 
+    //  (or sets them, the first time), for the choices that were tried in it
+    pub fn update(&mut self, config: &MutationConfig, counts: &MutationCounts) {
+        let follow = |rate: &mut Option<f64>, attempts: u64, successes: u64| {
+            if attempts > 0 {
+                let seen = (successes as f64)/(attempts as f64);
+                *rate = Some(match *rate {
+                    Some(r) => r + ADAPTATION_RATE*(seen - r),
+                    None => seen
+                });
+            }
+        };
+        for (i, rate) in self.mutation_success.iter_mut().enumerate() {
+            follow(rate, counts.attempts[i], counts.successes[i]);
+        }
+        let min_flips = config.min_flips as usize;
+        for (i, rate) in self.flip_success.iter_mut().enumerate() {
+            if let Some(&(attempts, successes)) = counts.flips.get(min_flips + i) {
+                follow(rate, attempts, successes);
+            }
+        }
+        self.share(config);
+    }
+
+    //  shares the probabilities according to the success rates 
+    //  (only between the mutations with positive weights)
+    fn share(&mut self, config: &MutationConfig) {
+        let weights: Vec<f64> = MUTATIONS.iter().map(|&m| config.weight(m) as f64).collect();
+        self.mutations = MutationRates::matching(&self.mutation_success, &weights);
+        self.flips = MutationRates::matching(&self.flip_success, &vec![1.0; self.flip_success.len()]);
+    }
+
+    //  the choices not tried yet count as doing as well as the others on average,
+    //  and as long as none of them has been tried, the probabilities follow the weights
+    fn matching(success: &[Option<f64>], weights: &[f64]) -> Vec<f64> {
+        let in_use: Vec<bool> = weights.iter().map(|&w| w > 0.0).collect();
+        let known: Vec<f64> = success.iter().zip(&in_use).filter(|p| *p.1)
+            .filter_map(|p| *p.0).collect();
+        if known.is_empty() {
+            let total: f64 = weights.iter().sum();
+            return weights.iter().map(|w| w/total).collect();
+        }
+        let mean = known.iter().sum::<f64>()/(known.len() as f64);
+        let n = in_use.iter().filter(|&&u| u).count() as f64;
+        let total: f64 = success.iter().zip(&in_use).filter(|p| *p.1).map(|p| p.0.unwrap_or(mean)).sum();
+        success.iter().zip(&in_use).map(|(&s, &u)| {
+            if !u { 0.0 } 
+            else if total > 0.0 { MIN_SHARE/n + (1.0 - MIN_SHARE)*s.unwrap_or(mean)/total }
+            else { 1.0/n }
+        }).collect()
+    }
+
+    //  picks an index with the given probabilities
+    fn pick<R: Rng>(probabilities: &[f64], rng: &mut R) -> usize {
+        let mut r = rng.gen::<f64>();
+        for (i, &p) in probabilities.iter().enumerate() {
+            if r < p { return i; }
+            r -= p;
+        }
+        //  rounding errors
+        probabilities.iter().rposition(|&p| p > 0.0).unwrap()
+    }
+
+    //  the probabilities as text, to follow the adaptation
+    pub fn describe(&self, config: &MutationConfig) -> String {
+        let mutations: Vec<String> = MUTATIONS.iter().zip(&self.mutations)
+            .filter(|&(&m, _)| config.weight(m) > 0)
+            .map(|(m, p)| format!("{} {:.2}", m.name(), p)).collect();
+        let flips: Vec<String> = self.flips.iter().enumerate()
+            .map(|(i, p)| format!("{} {:.2}", config.min_flips as usize + i, p)).collect();
+        format!("mutations: {}; flips: {}", mutations.join(", "), flips.join(", "))
+    }
+}
+
+//  tries random combinations of flips on the layout, returning the best scoring one.
+//  The flips are applied to (and undone on) the occupancy grid, so each attempt
+//  only re-scores what has changed. The mutations are picked by the weights, 
+//  unless the adapted rates are given
+pub fn mutate<'a, R: Rng>(layout: &Layout<'a>, attempts: u32, config: &MutationConfig,
+    rates: Option<&MutationRates>, rng: &mut R, counts: &mut MutationCounts) -> Layout<'a>
+{
+    let mut max_score = -f64::MAX;
+    let mut res = layout.clone();
+    let mut occ = Occupancy::new(layout);
+    let start_score = occ.score();
+    for _ in 0..attempts {
+        let (used, num_flips) = random_flips(&mut occ, config, rates, rng);
+        let score = occ.score();
+        counts.record(used, num_flips, score > start_score);
+        if score > max_score {
+            max_score = score;
+            res = occ.layout().clone();
+        }
+        occ.undo();
+    }
+    res
+}
+
+//  applies a random combination of flips, returning the bit set of the mutations used
+//  and the number of flips
+pub fn random_flips<R: Rng>(occ: &mut Occupancy, config: &MutationConfig, 
+    rates: Option<&MutationRates>, rng: &mut R) -> (u32, usize) 
+{
+    let nshapes = occ.layout().pos.len();
+    let bundle = occ.layout().bundle;
+    let num_flips = match rates {
+        Some(rates) => config.min_flips as usize + MutationRates::pick(&rates.flips, rng),
+        None => rng.gen_range(config.min_flips as usize, config.max_flips as usize + 1)
+    };
+    let mut used = 0;
+    for _ in 0..num_flips {
+        let mutation = match rates {
+            Some(rates) => MUTATIONS[MutationRates::pick(&rates.mutations, rng)],
+            None => config.pick(rng)
+        };
+        let pidx1 = rng.gen_range(0, nshapes);
+        let pidx2 = rng.gen_range(0, nshapes);
+        match mutation {
+            Mutation::Variant => {
+                let mut p1 = occ.layout().pos[pidx1].clone();
+                let nvar1 = bundle[p1.shape as usize].len() as u16;
+                p1.var = rng.gen_range(0, nvar1);
+                occ.set(pidx1, p1);
+
+                let mut p2 = occ.layout().pos[pidx2].clone();
+                let nvar2 = bundle[p2.shape as usize].len() as u16;
+                p2.var = rng.gen_range(0, nvar2);
+                occ.set(pidx2, p2);
+            },
+            Mutation::Offset => {
+                let offsets = occ.layout().grid().fill_neighbors();
+                let offs = offsets[rng.gen_range(0, offsets.len())];
+                for k in pidx1..(pidx2 + 1) {
+                    let mut p = occ.layout().pos[k].clone();
+                    p.x += offs[0];
+                    p.y += offs[1];
+                    occ.set(k, p);
+                }
+            },
+            Mutation::Swap => {
+                let p1 = occ.layout().pos[pidx1].clone();
+                let p2 = occ.layout().pos[pidx2].clone();
+                occ.set(pidx1, Position { shape: p2.shape, var: p2.var, ..p1 });
+                occ.set(pidx2, Position { shape: p1.shape, var: p1.var, ..p2 });
+            },
+            Mutation::Reverse => {
+                //  the shapes stay where they are, only the chain order changes
+                let (mut i, mut j) = if pidx1 < pidx2 { (pidx1, pidx2) } else { (pidx2, pidx1) };
+                while i < j {
+                    let p1 = occ.layout().pos[i].clone();
+                    let p2 = occ.layout().pos[j].clone();
+                    occ.set(i, p2);
+                    occ.set(j, p1);
+                    i += 1;
+                    j -= 1;
+                }
+            },
+            Mutation::Rotate => {
+                let p = occ.layout().rotate_in_place(pidx1, rng.gen());
+                occ.set(pidx1, p);
+            },
+            Mutation::Refit => {
+                let p = occ.layout().refit(pidx1);
+                occ.set(pidx1, p);
+            }
+        }
+        used |= 1 << (mutation as u32);
+    }
+    (used, num_flips)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+    use polyomino::layout::parse_bundle;
+
+    #[test]
+    fn test_random_flips() {
+        let bundle = parse_bundle(include_str!("../../data/tetromino.txt"), true, true).unwrap();
+        let seed: &[_] = &[1, 2, 3];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let mut layout = Layout::new(&bundle);
+        layout.shuffle(&mut rng);
+        layout.arrange_circle(3.0);
+
+        let mut counts = MutationCounts::default();
+        for &m in MUTATIONS.iter() {
+            //  a single mutation at a time
+            let mut config = MutationConfig { min_flips: 1, max_flips: 3,
+                variant: 0, offset: 0, swap: 0, ..MutationConfig::default() };
+            *config.weight_mut(m) = 1;
+            config.validate().unwrap();
+            let mut occ = Occupancy::new(&layout);
+            for _ in 0..20 {
+                let (used, num_flips) = random_flips(&mut occ, &config, None, &mut rng);
+                assert_eq!(1 << (m as u32), used);
+                assert!((1..=3).contains(&num_flips));
+                //  every shape is still used once
+                assert!(Layout::from_positions(&bundle, occ.layout().pos.clone()).is_ok());
+                assert_eq!(occ.layout().score(), occ.score());
+                counts.record(used, num_flips, occ.score() > layout.score());
+                occ.undo();
+            }
+            assert!(occ.layout() == &layout);
+
+            let stats = counts.stats(&config);
+            assert_eq!(1, stats.len());
+            assert_eq!(m.name(), stats[0].name);
+            assert_eq!(20, stats[0].attempts);
+            assert_eq!(Some(m), Mutation::from_name(m.name()));
+        }
+
+        //  the adapted probabilities follow the success rates
+        let config = MutationConfig { min_flips: 1, max_flips: 2, refit: 2, adaptive: true, 
+            ..MutationConfig::default() };
+        let mut rates = MutationRates::start(&config, None).unwrap();
+        assert_eq!(vec![0.2, 0.2, 0.2, 0.0, 0.0, 0.4], rates.mutations.iter()
+            .map(|p| (p*1000.0).round()/1000.0).collect::<Vec<_>>());
+        assert_eq!(vec![0.5, 0.5], rates.flips);
+        let mut counts = MutationCounts::default();
+        for i in 0..100 {
+            counts.record(1 << (Mutation::Swap as u32), 2, i%2 == 0);
+            for m in &[Mutation::Variant, Mutation::Offset, Mutation::Refit] {
+                counts.record(1 << (*m as u32), 1, false);
+            }
+        }
+        //  the first counts set the estimates as they are
+        rates.update(&config, &counts);
+        assert_eq!(Some(0.5), rates.mutation_success[Mutation::Swap as usize]);
+        assert_eq!(Some(0.0), rates.mutation_success[Mutation::Refit as usize]);
+        assert_eq!(None, rates.mutation_success[Mutation::Reverse as usize]);
+        assert_eq!(vec![Some(0.0), Some(0.5)], rates.flip_success);
+        for _ in 0..19 { rates.update(&config, &counts); }
+        assert!(rates.mutations[Mutation::Swap as usize] > 0.8);
+        assert!(rates.mutations[Mutation::Variant as usize] >= 0.05);
+        assert!(rates.mutations[Mutation::Reverse as usize] == 0.0);
+        assert!(rates.flips[1] > 0.8 && rates.flips[0] >= 0.1);
+        assert!((rates.mutations.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        let mut occ = Occupancy::new(&layout);
+        for _ in 0..20 {
+            let (used, num_flips) = random_flips(&mut occ, &config, Some(&rates), &mut rng);
+            assert!(used & (1 << (Mutation::Reverse as u32)) == 0 && num_flips <= 2);
+        }
+        assert!(MutationRates::start(&MutationConfig::default(), None).is_none());
+
+        let no_flips = MutationConfig { min_flips: 0, ..MutationConfig::default() };
+        assert!(no_flips.validate().is_err());
+        let no_weights = MutationConfig { variant: 0, offset: 0, swap: 0, ..MutationConfig::default() };
+        assert!(no_weights.validate().is_err());
+    }
+}
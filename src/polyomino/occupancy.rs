@@ -0,0 +1,393 @@
+// ------------------------------------------------------------------------------------------------
+// occupancy.rs
+// ------------------------------------------------------------------------------------------------
+use polyomino::math::{Vec2i};
+use polyomino::layout::{Layout, Position, COFFS};
+
+//  extra cells kept around the layout when (re)allocating the grid
+const GRID_MARGIN : i32 = 8;
+//  maximum grid width to keep the occupied cells as bit rows for
+const MAX_BITS_WIDTH : i32 = 128;
+
+//  Layout along with the grid of the cells its shapes occupy, which is kept up to date
+//  as the shapes move (and move back), so that re-scoring doesn't start from scratch.
+//  Gives the same score as Layout::score
+pub struct Occupancy<'a> {
+    layout: Layout<'a>,
+    grid: bool,                 //  false if the layout is too wide for the bit rows
+    rows: Vec<u128>,            //  occupied cells of each grid row as bits
+    shared: Vec<u128>,          //  cells covered by more than one shape
+    counts: Vec<u16>,           //  number of extra shapes covering each of those
+    origin: Vec2i,              //  layout coordinates of the grid's (0, 0)
+    width: i32,
+    height: i32,
+    links: Vec<Option<i32>>,    //  cached distances between the shape and the next one
+    undo_log: Vec<(usize, Position)>,   //  previous positions of the moved shapes
+    link_log: Vec<(usize, Option<i32>)>,    //  previous distances of the changed links
+    base: Option<(usize, usize, usize)>,    //  enclosed area and its top/bottom grid rows 
+                                            //  at the last commit
+    region: Vec<u128>,          //  enclosed cells at the last commit, as bit rows
+    fill: Vec<u128>,            //  scratch rows for the flood fill
+    touched: Vec<(usize, usize)>,   //  grid rows the moved shapes left or took since then
+    queue: Vec<usize>,          //  rows for the flood fill to revisit
+    queued: Vec<bool>,          //  whether each row is in the queue
+}
+
+//  "dilates" the row by one cell to both sides
+fn dilate(row: u128) -> u128 {
+    row | (row << 1) | (row >> 1)
+}
+
+//  extends the seed bits along the runs of the free bits they are in
+fn spread(seeds: u128, free: u128) -> u128 {
+    let up = (seeds.wrapping_add(free) ^ free) & free;
+    let (rs, rf) = (seeds.reverse_bits(), free.reverse_bits());
+    let down = ((rs.wrapping_add(rf) ^ rf) & rf).reverse_bits();
+    seeds | up | down
+}
+
+impl<'a> Occupancy<'a> {
+    //  constructor
+    pub fn new(layout: &Layout<'a>) -> Occupancy<'a> {
+        let mut res = Occupancy {
+            layout: layout.clone(),
+            grid: layout.bundle.iter().all(|vars| vars.iter().all(|s| !s.bit_rows().is_empty())),
+            rows: vec![],
+            shared: vec![],
+            counts: vec![],
+            origin: Vec2i::new(0, 0),
+            width: 0,
+            height: 0,
+            links: vec![None; layout.pos.len()],
+            undo_log: vec![],
+            link_log: vec![],
+            base: None,
+            region: vec![],
+            fill: vec![],
+            touched: vec![],
+            queue: vec![],
+            queued: vec![],
+        };
+        res.rebuild();
+        res.commit();
+        res
+    }
+
+    //  the current layout
+    pub fn layout(&self) -> &Layout<'a> {
+        &self.layout
+    }
+
+    //  reallocates the grid around the current layout
+    fn rebuild(&mut self) {
+        //  the enclosed cells are not where they were anymore
+        self.base = None;
+        if !self.grid { return; }
+        let (lt, rb) = self.layout.bounds();
+        self.origin = Vec2i::new(lt.x - GRID_MARGIN, lt.y - GRID_MARGIN);
+        self.width = rb.x - lt.x + 2*GRID_MARGIN;
+        self.height = rb.y - lt.y + 2*GRID_MARGIN;
+        if self.width > MAX_BITS_WIDTH {
+            self.grid = false;
+            return;
+        }
+        self.rows = vec![0; self.height as usize];
+        self.shared = vec![0; self.height as usize];
+        self.counts = vec![0; (self.width*self.height) as usize];
+        self.queued = vec![false; self.height as usize];
+        for i in 0..self.layout.pos.len() {
+            let p = self.layout.pos[i].clone();
+            self.cover(&p, true);
+        }
+    }
+
+    //  adds the shape's squares to the occupied cells, or takes them away
+    fn cover(&mut self, p: &Position, add: bool) {
+        let shape = &self.layout.bundle[p.shape as usize][p.var as usize];
+        let x = p.x - self.origin.x;
+        let y0 = (p.y - self.origin.y) as usize;
+        let bit_rows = shape.bit_rows();
+        let y1 = y0 + bit_rows.len();
+        let rows = &mut self.rows[y0..y1];
+        let shared = &mut self.shared[y0..y1];
+        for (k, &row) in bit_rows.iter().enumerate() {
+            let bits = (row as u128) << x;
+            let mut extra = if add { rows[k] & bits } else { shared[k] & bits };
+            if add {
+                rows[k] |= bits;
+            } else {
+                //  the cells covered just once are free now
+                rows[k] &= !(bits & !extra);
+            }
+            while extra != 0 {
+                let bit = extra & extra.wrapping_neg();
+                let idx = (y0 + k)*self.width as usize + bit.trailing_zeros() as usize;
+                if add { self.counts[idx] += 1; } else { self.counts[idx] -= 1; }
+                if self.counts[idx] == 0 { shared[k] &= !bit; } 
+                else { shared[k] |= bit; }
+                extra ^= bit;
+            }
+        }
+        if self.base.is_some() { self.touched.push((y0, y1)); }
+    }
+
+    //  returns true if the shape fits inside the grid, leaving a cell around it
+    //  (and one more row below, as the flood fill looks a row past the bounds)
+    fn fits(&self, p: &Position) -> bool {
+        let shape = self.layout.shape_by_pos(p);
+        let x = p.x - self.origin.x;
+        let y = p.y - self.origin.y;
+        x >= 1 && y >= 1 && x + shape.width < self.width && y + shape.height + 1 < self.height
+    }
+
+    //  moves the shape at the given chain index, remembering where it was
+    pub fn set(&mut self, i: usize, p: Position) {
+        let prev = self.layout.pos[i].clone();
+        if prev == p { return; }
+        self.undo_log.push((i, prev.clone()));
+        let n = self.layout.pos.len();
+        for &k in &[i, (i + n - 1)%n] {
+            self.link_log.push((k, self.links[k]));
+            self.links[k] = None;
+        }
+        self.place(i, prev, p);
+    }
+
+    fn place(&mut self, i: usize, prev: Position, p: Position) {
+        if !self.grid {
+            self.layout.pos[i] = p;
+        } else if self.fits(&p) {
+            self.cover(&prev, false);
+            self.cover(&p, true);
+            self.layout.pos[i] = p;
+        } else {
+            self.layout.pos[i] = p;
+            self.rebuild();
+        }
+    }
+
+    //  forgets the moves made so far, so they can't be undone anymore,
+    //  and remembers the enclosed area
+    pub fn commit(&mut self) {
+        self.undo_log.clear();
+        self.link_log.clear();
+        self.touched.clear();
+        self.base = None;
+        if self.grid {
+            if let Some(n) = self.flood_fill() {
+                let top = self.fill.iter().position(|&r| r != 0).unwrap();
+                let bottom = self.fill.iter().rposition(|&r| r != 0).unwrap();
+                self.base = Some((n, top, bottom));
+                self.region = self.fill.clone();
+            }
+        }
+    }
+
+    //  moves the shapes back to where they were at the last commit
+    pub fn undo(&mut self) {
+        let base = self.base;
+        while let Some((i, p)) = self.undo_log.pop() {
+            let cur = self.layout.pos[i].clone();
+            self.place(i, cur, p);
+        }
+        while let Some((k, d)) = self.link_log.pop() {
+            self.links[k] = d;
+        }
+        self.touched.clear();
+        //  unless the grid had to grow, the enclosed area is the same again
+        if base.is_some() && self.base.is_none() {
+            self.commit();
+        }
+    }
+
+    //  re-fills the enclosed area starting from the one at the last commit, visiting only
+    //  the rows around the cells the moved shapes left or took. Returns None if that
+    //  can't be done (a shape moved into the area, or the fill would start elsewhere),
+    //  Some(None) if the area leaks now
+    fn refill(&mut self) -> Option<Option<usize>> {
+        let (n, top, bottom) = self.base?;
+        let (region, rows) = (&self.region, &self.rows);
+        if self.touched.iter().any(|&(y0, y1)| (y0..y1).any(|y| region[y] & rows[y] != 0)) {
+            return None;
+        }
+        let (lt, rb) = self.layout.bounds();
+        let (ox, oy) = (lt.x - self.origin.x, lt.y - self.origin.y);
+        let w = rb.x - lt.x + 1;
+        let h = rb.y - lt.y + 1;
+        let (sx, sy) = self.fill_start(ox, oy, w, h);
+        if self.region[(sy + oy) as usize] & (1 << (sx + ox)) == 0 { return None; }
+
+        //  the area is still there, unless it gets out of the (changed) bounds
+        let window = (!0u128 >> (128 - w)) << ox;
+        let edges = (1u128 << ox) | (1u128 << (ox + w - 1));
+        let (oy, h) = (oy as usize, h as usize);
+        if top <= oy || bottom >= oy + h - 1 { return Some(None); }
+        if self.region[top..(bottom + 1)].iter().any(|r| r & (edges | !window) != 0) { 
+            return Some(None); 
+        }
+        self.fill.clone_from(&self.region);
+        self.queue.clear();
+        for i in 0..self.touched.len() {
+            let (y0, y1) = self.touched[i];
+            for y in (y0 - 1)..(y1 + 1) {
+                if y >= oy && y < oy + h { self.push_row(y); }
+            }
+        }
+        Some(self.grow(oy, h, window, edges).map(|added| n + added))
+    }
+
+    //  queues the row for the flood fill, unless it's already there
+    fn push_row(&mut self, y: usize) {
+        if !self.queued[y] {
+            self.queued[y] = true;
+            self.queue.push(y);
+        }
+    }
+
+    //  grows the filled rows around the queued ones until they stop changing,
+    //  returning the number of cells added, or None if the fill gets to the bounds
+    fn grow(&mut self, oy: usize, h: usize, window: u128, edges: u128) -> Option<usize> {
+        let mut added = 0;
+        while let Some(y) = self.queue.pop() {
+            self.queued[y] = false;
+            let free = !self.rows[y] & window;
+            let near = dilate(self.fill[y - 1] | self.fill[y] | self.fill[y + 1]);
+            let row = self.fill[y] | spread(near & free, free);
+            if row != self.fill[y] {
+                if y == oy || y == oy + h - 1 || row & edges != 0 { 
+                    while let Some(y) = self.queue.pop() { self.queued[y] = false; }
+                    return None;
+                }
+                added += (row.count_ones() - self.fill[y].count_ones()) as usize;
+                self.fill[y] = row;
+                self.push_row(y - 1);
+                self.push_row(y + 1);
+            }
+        }
+        Some(added)
+    }
+
+    //  picks the flood fill starting point inside the bounds, the same way Layout::flood_fill does
+    fn fill_start(&self, ox: i32, oy: i32, w: i32, h: i32) -> (i32, i32) {
+        let is_set = |x: i32, y: i32| self.rows[(y + oy) as usize] & (1 << (x + ox)) != 0;
+        let mut sx = w/2;
+        let mut sy = h/2;
+        if is_set(sx, sy) {
+            for offs in COFFS.iter() {
+                let cx = sx + offs[0];
+                let cy = sy + offs[1];
+                if !is_set(cx, cy) {
+                    sx = cx; sy = cy;
+                    break;
+                }
+            }
+        }
+        (sx, sy)
+    }
+
+    //  fills the enclosed area the same way Layout::flood_fill does, but a whole row 
+    //  at a time, leaving the filled cells in the scratch rows
+    fn flood_fill(&mut self) -> Option<usize> {
+        let (lt, rb) = self.layout.bounds();
+        let (ox, oy) = (lt.x - self.origin.x, lt.y - self.origin.y);
+        let w = rb.x - lt.x + 1;
+        let h = rb.y - lt.y + 1;
+        let window = (!0u128 >> (128 - w)) << ox;
+        let edges = (1u128 << ox) | (1u128 << (ox + w - 1));
+        let (sx, sy) = self.fill_start(ox, oy, w, h);
+
+        self.fill.clear();
+        self.fill.resize(self.height as usize, 0);
+        let (oy, h) = (oy as usize, h as usize);
+        let start = sy as usize + oy;
+        if start == oy || start == oy + h - 1 || (1 << (sx + ox)) & edges != 0 { return None; }
+        self.fill[start] = 1 << (sx + ox);
+        self.queue.clear();
+        for y in (start - 1)..(start + 2) { self.push_row(y); }
+        self.grow(oy, h, window, edges).map(|added| added + 1)
+    }
+
+    //  computes the same score as Layout::score, re-measuring only
+    //  the distances between the shapes that moved and their neighbors
+    pub fn score(&mut self) -> f64 {
+        let area = if self.grid {
+            match self.refill() {
+                Some(area) => area,
+                None => self.flood_fill()
+            }
+        } else {
+            self.layout.flood_fill(|_, _| {})
+        };
+        if let Some(n) = area {
+            return n as f64;
+        }
+        let n = self.layout.pos.len();
+        let mut dist = 0.0;
+        for i in 0..n {
+            let d = match self.links[i] {
+                Some(d) => d,
+                None => {
+                    let p = &self.layout.pos[i];
+                    let p1 = &self.layout.pos[(i + 1)%n];
+                    let d = Layout::distance(self.layout.shape_by_pos(p),
+                        self.layout.shape_by_pos(p1), &p.p(), &p1.p()).abs();
+                    self.links[i] = Some(d);
+                    d
+                }
+            };
+            dist += d as f64;
+        }
+        -dist
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, StdRng};
+    use polyomino::layout::parse_bundle;
+    use polyomino::sketch::parse_sketch;
+
+    //  moves random shapes around, checking the score against the one from scratch
+    fn random_walk(mut layout: Layout, max_step: i32, rng: &mut StdRng) {
+        let mut occ = Occupancy::new(&layout);
+        assert_eq!(layout.score(), occ.score());
+        for _ in 0..500 {
+            for _ in 0..rng.gen_range(1, 4) {
+                let i = rng.gen_range(0, layout.pos.len());
+                let mut p = occ.layout().pos[i].clone();
+                p.x += rng.gen_range(-max_step, max_step + 1);
+                p.y += rng.gen_range(-max_step, max_step + 1);
+                if rng.gen() {
+                    p.var = rng.gen_range(0, layout.bundle[p.shape as usize].len() as u16);
+                }
+                occ.set(i, p);
+            }
+            assert_eq!(occ.layout().score(), occ.score());
+            if rng.gen_weighted_bool(4) {
+                occ.commit();
+                layout = occ.layout().clone();
+            } else {
+                occ.undo();
+                assert!(occ.layout() == &layout);
+                assert_eq!(layout.score(), occ.score());
+            }
+        }
+    }
+
+    #[test]
+    fn test_occupancy_score() {
+        let bundle = parse_bundle(include_str!("../../data/tetromino.txt"), true, true).unwrap();
+        let seed: &[_] = &[1, 2, 3];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let mut layout = Layout::new(&bundle);
+        layout.shuffle(&mut rng);
+        layout.arrange_circle(3.0);
+        random_walk(layout, 3, &mut rng);
+
+        //  small moves around a closed one
+        let layout = parse_sketch(".BB..\nABBCC\nA...C\nA...C\nA...E\nDDDEE\n.D.E.\n", 
+            &bundle).unwrap();
+        random_walk(layout, 1, &mut rng);
+    }
+}
@@ -3,12 +3,19 @@
 // ------------------------------------------------------------------------------------------------
 use std::cmp;
 use std::f64;
+use std::hash::{Hash, Hasher};
 use std::f64::consts::{PI};
 use super::math::*;
+use super::grid::Grid;
+use super::error::PolyfarmError;
+
+//  maximum shape width to keep the bit masks for (with a square of margin at each side)
+const MAX_BITS_WIDTH : i32 = 62;
 
 //  neighbor offsets, horizontal/vertical
 pub const OFFS: [[i32; 2]; 4] = [[1, 0], [0, 1], [-1, 0], [0, -1]];
 
+#[derive(Clone, Copy)]
 pub enum Rotation {
     None = 0, // no rotation
     CW90 = 1, // 90 degrees clockwise
@@ -16,64 +23,211 @@ pub enum Rotation {
     CW270 = 3, // 270 degrees clockwise
 }
 
-// Polyomino shape
+//  Transformation of a shape variant relative to the original shape:
+//  mirrored horizontally (if set) first, then rotated clockwise
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Transform {
+    pub mirrored: bool,
+    pub rotation: u8,   //  number of clockwise rotation steps (by 90 degrees, 60 on the hex grid)
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform { mirrored: false, rotation: 0 }
+    }
+}
+
+//  Transformations a shape may take in the layouts (its symmetry group, so to say)
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Transforms {
+    #[default]
+    Free,       //  rotations and reflections
+    OneSided,   //  rotations only
+    Fixed,      //  none
+}
+
+impl Transforms {
+    pub fn name(self) -> &'static str {
+        match self {
+            Transforms::Free => "free",
+            Transforms::OneSided => "one-sided",
+            Transforms::Fixed => "fixed",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Transforms> {
+        [Transforms::Free, Transforms::OneSided, Transforms::Fixed].iter()
+            .find(|t| t.name() == name).cloned()
+    }
+
+    //  whether a variant with the given transform may be used
+    pub fn allows(self, t: Transform) -> bool {
+        match self {
+            Transforms::Free => true,
+            Transforms::OneSided => !t.mirrored,
+            Transforms::Fixed => t == Transform::identity(),
+        }
+    }
+}
+
+//  Symmetry group of a shape: the cyclic ones (Cn) have just the rotations by 360/n degrees,
+//  the dihedral ones (Dn) have as many mirror axes on top of them
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SymmetryGroup {
+    C1,     //  no symmetry at all, as the F-pentomino
+    C2,     //  half turn, as the Z-pentomino
+    C3,     //  third of a turn (hex grid only)
+    C4,     //  quarter turn (square grid only)
+    C6,     //  sixth of a turn (hex grid only)
+    D1,     //  a single mirror axis, as the T-pentomino
+    D2,     //  two axes and the half turn, as the I-pentomino
+    D3,     //  three axes, as the triangle of three hexagons
+    D4,     //  four axes, as the X-pentomino
+    D6,     //  six axes, as the single hexagon
+}
+
+impl SymmetryGroup {
+    pub fn name(self) -> &'static str {
+        match self {
+            SymmetryGroup::C1 => "C1",
+            SymmetryGroup::C2 => "C2",
+            SymmetryGroup::C3 => "C3",
+            SymmetryGroup::C4 => "C4",
+            SymmetryGroup::C6 => "C6",
+            SymmetryGroup::D1 => "D1",
+            SymmetryGroup::D2 => "D2",
+            SymmetryGroup::D3 => "D3",
+            SymmetryGroup::D4 => "D4",
+            SymmetryGroup::D6 => "D6",
+        }
+    }
+
+    //  number of the transformations that leave the shape as it is
+    pub fn order(self) -> usize {
+        match self {
+            SymmetryGroup::C1 => 1,
+            SymmetryGroup::C2 | SymmetryGroup::D1 => 2,
+            SymmetryGroup::C3 => 3,
+            SymmetryGroup::C4 | SymmetryGroup::D2 => 4,
+            SymmetryGroup::C6 | SymmetryGroup::D3 => 6,
+            SymmetryGroup::D4 => 8,
+            SymmetryGroup::D6 => 12,
+        }
+    }
+}
+
+// Polyomino (or polyhex) shape
 pub struct Shape {
-    pub squares: Vec<Vec2i>,
+    pub squares: Vec<Vec2i>,    //  the cells (hexagons too, in the axial coordinates)
     pub width: i32,
     pub height: i32,
 
     pub boundary: Vec<Vec2i>,
+    pub transform: Transform,   //  how the shape was obtained from the original one
+    pub transforms: Transforms, //  transformations allowed for the shape
+    pub name: Option<String>,   //  name given in the shape file, if any
+    pub color: Option<String>,  //  fill color given in the shape file (hex RGB), if any
+    pub grid: Grid,             //  square or hexagonal cells
     mask: Vec<bool>,
+    bits: Vec<u64>,             //  mask rows as bits (empty if the shape is too wide or hexagonal)
+    border_bits: Vec<u64>,      //  the same, grown by a square in every direction 
+                                //  (shifted by a square to the right and down)
 }
 
 // compare with [x, y]
 impl PartialEq for Shape {
     fn eq(&self, rhs: &Shape) -> bool {
-        self.width == rhs.width && self.mask == rhs.mask
+        self.width == rhs.width && self.mask == rhs.mask && self.grid == rhs.grid
+    }
+}
+
+impl Eq for Shape {}
+
+//  hashes the same things the comparison looks at
+impl Hash for Shape {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.width.hash(state);
+        self.mask.hash(state);
+        self.grid.hash(state);
     }
 }
 
 impl Shape {
     //  constructor
     pub fn new(squares: Vec<Vec2i>) -> Shape {
+        Shape::with_grid(squares, Grid::Square)
+    }
+
+    //  constructor for the cells of the given grid
+    pub fn with_grid(squares: Vec<Vec2i>, grid: Grid) -> Shape {
         let (w, h) = Shape::extents(&squares);
         let mask = Shape::build_mask(&squares);
-        let boundary = Shape::build_boundary(&squares, &mask);
+        let boundary = Shape::build_boundary(&squares, &mask, grid);
+        let (bits, border_bits) = if grid == Grid::Square { Shape::build_bits(&squares) } 
+                                  else { (vec![], vec![]) };
         let mut squares = squares.clone();
         squares.sort();
-        Shape { width: w, height: h, squares: squares, mask: mask, boundary: boundary, }
+        Shape { width: w, height: h, squares, mask, boundary, bits, border_bits, 
+            transform: Transform::identity(), transforms: Transforms::Free, name: None, color: None,
+            grid }
+    }
+
+    //  moves the cells so that the minimum coordinates are (0, 0)
+    fn to_corner(squares: Vec<Vec2i>) -> Vec<Vec2i> {
+        let min_x = squares.iter().map(|s| s.x).min().unwrap();
+        let min_y = squares.iter().map(|s| s.y).min().unwrap();
+        squares.into_iter().map(|s| Vec2i::new(s.x - min_x, s.y - min_y)).collect()
     }
 
     // finds (width, height) of the square coordinate list
-    fn extents(squares: &Vec<Vec2i>) -> (i32, i32) {
+    fn extents(squares: &[Vec2i]) -> (i32, i32) {
         let w = squares.iter().map(|v| v.x).max().unwrap();
         let h = squares.iter().map(|v| v.y).max().unwrap();
         (w + 1, h + 1)
     }
 
     // builds a boolean mask (bitmap) for a square list
-    fn build_mask(squares: &Vec<Vec2i>) -> Vec<bool> {
+    fn build_mask(squares: &[Vec2i]) -> Vec<bool> {
         let (w, h) = Shape::extents(squares);
         let mut res = vec![false; (w*h) as usize];
         for p in squares {
-            let idx = p.x + (p.y * (w as i32));
+            let idx = p.x + (p.y * w);
             res[idx as usize] = true;
         }
         res
     }
 
+    // builds the bit rows of the mask, and of the mask grown by the neighbor squares
+    fn build_bits(squares: &[Vec2i]) -> (Vec<u64>, Vec<u64>) {
+        let (w, h) = Shape::extents(squares);
+        if w > MAX_BITS_WIDTH { return (vec![], vec![]); }
+        let mut bits = vec![0; h as usize];
+        for p in squares {
+            bits[p.y as usize] |= 1 << p.x;
+        }
+        let mut border_bits = vec![0; (h + 2) as usize];
+        for (y, &row) in bits.iter().enumerate() {
+            let row = row << 1;
+            border_bits[y] |= row;
+            border_bits[y + 1] |= row | (row << 1) | (row >> 1);
+            border_bits[y + 2] |= row;
+        }
+        (bits, border_bits)
+    }
+
     // creates a list of boundary square coordinates
-    fn build_boundary(squares: &Vec<Vec2i>, mask: &Vec<bool>) -> Vec<Vec2i> {
+    fn build_boundary(squares: &[Vec2i], mask: &[bool], grid: Grid) -> Vec<Vec2i> {
         let (w, h) = Shape::extents(squares);
         let mut res = vec![];
         for sq in squares {
-            for offs in OFFS.iter() {
+            for offs in grid.neighbors() {
                 let x = sq.x + offs[0];
                 let y = sq.y + offs[1];
                 let in_area = x >= 0 && y >= 0 && x < w && y < h;
                 let is_set = in_area && mask[(x + y * w) as usize];
                 if !is_set {
-                    res.push(Vec2i { x: x, y: y });
+                    res.push(Vec2i { x, y });
                 }
             }
         }
@@ -83,8 +237,13 @@ impl Shape {
     }
 
     // parses a shape from string representation (newline separated)
-    pub fn parse(input: &str) -> Shape {
-        let squares = input.lines()
+    pub fn parse(input: &str) -> Result<Shape, PolyfarmError> {
+        //  tabs have ambiguous width, so would silently shift the squares
+        if let Some(j) = input.lines().position(|line| line.contains('\t')) {
+            return Err(PolyfarmError::parse(0, j + 1, 
+                "tab characters are not allowed, use spaces instead"));
+        }
+        let squares: Vec<Vec2i> = input.lines()
         .enumerate()
         .flat_map(|(j, line)| {
             line.chars()
@@ -95,60 +254,219 @@ impl Shape {
             })
         })
         .collect();
-        Shape::new(squares)
+        if squares.is_empty() {
+            return Err(PolyfarmError::parse(0, 1, "empty shape"));
+        }
+        Ok(Shape::new(squares))
+    }
+
+    //  parses a polyhex from its drawing, the hexagons being in the staggered rows:
+    //
+    //       O O
+    //      O . O
+    //       O O
+    //
+    //  so that the neighbors in a row are two characters apart, 
+    //  and the ones in the next row are a character to the left or to the right
+    pub fn parse_hex(input: &str) -> Result<Shape, PolyfarmError> {
+        if let Some(j) = input.lines().position(|line| line.contains('\t')) {
+            return Err(PolyfarmError::parse(0, j + 1, 
+                "tab characters are not allowed, use spaces instead"));
+        }
+        let mut squares = vec![];
+        let mut parity = None;
+        for (j, line) in input.lines().enumerate() {
+            for (i, c) in line.chars().enumerate() {
+                if c == ' ' { continue; }
+                let (i, j) = (i as i32, j as i32);
+                //  every hexagon is at an even (or every one at an odd) column, counting the row
+                let p = *parity.get_or_insert((i + j)%2);
+                if (i + j)%2 != p {
+                    return Err(PolyfarmError::parse(0, j as usize + 1, 
+                        "hexagons must be two columns apart, and a column off the next row's"));
+                }
+                squares.push(Vec2i::new((i - j - p)/2, j));
+            }
+        }
+        if squares.is_empty() {
+            return Err(PolyfarmError::parse(0, 1, "empty shape"));
+        }
+        Ok(Shape::with_grid(Shape::to_corner(squares), Grid::Hex))
     }
 
     //  returns mirrored shape
     fn mirrored(&self) -> Shape {
-        let squares = self.squares.iter().map(|s| {
-            Vec2i {x: self.width - s.x - 1, y: s.y,}
-        });
-        Shape::new(squares.collect())
-    }
-
-    //  returns shape, rotated by given amount
-    fn rotated(&self, rot: Rotation) -> Shape {
-        let (w, h) = (self.width, self.height);
-        let squares = self.squares.iter().map(|s| {
-            let (x, y) = match rot {
-                Rotation::CW90 => (h - s.y - 1, s.x),
-                Rotation::CW180 => (w - s.x - 1, h - s.y - 1),
-                Rotation::CW270 => (s.y, w - s.x - 1),
-                Rotation::None => (s.x, s.y),
-            };
-            Vec2i { x: x, y: y }
-        });
-        Shape::new(squares.collect())
+        let squares = match self.grid {
+            Grid::Square => self.squares.iter().map(|s| {
+                Vec2i {x: self.width - s.x - 1, y: s.y,}
+            }).collect(),
+            Grid::Hex => Shape::to_corner(self.squares.iter().map(|&s| self.grid.mirror(s)).collect())
+        };
+        let mut res = Shape::with_grid(squares, self.grid);
+        //  mirroring after a rotation is the same as mirroring before the opposite one
+        let n = self.grid.rotations();
+        res.transform = Transform {
+            mirrored: !self.transform.mirrored,
+            rotation: (n - self.transform.rotation)%n,
+        };
+        res
+    }
+
+    //  returns shape, rotated by given amount (of the grid's rotation steps)
+    pub fn rotated(&self, rot: Rotation) -> Shape {
+        self.turned(rot as u8)
+    }
+
+    //  returns the shape, turned clockwise by the given number of the grid's rotation steps
+    pub fn turned(&self, steps: u8) -> Shape {
+        let n = self.grid.rotations();
+        let steps = steps%n;
+        let squares = match self.grid {
+            Grid::Square => {
+                let (w, h) = (self.width, self.height);
+                self.squares.iter().map(|s| {
+                    let (x, y) = match steps {
+                        1 => (h - s.y - 1, s.x),
+                        2 => (w - s.x - 1, h - s.y - 1),
+                        3 => (s.y, w - s.x - 1),
+                        _ => (s.x, s.y),
+                    };
+                    Vec2i { x, y }
+                }).collect()
+            },
+            Grid::Hex => Shape::to_corner(self.squares.iter()
+                .map(|&s| (0..steps).fold(s, |p, _| self.grid.turn(p))).collect())
+        };
+        let mut res = Shape::with_grid(squares, self.grid);
+        res.transform = Transform {
+            mirrored: self.transform.mirrored,
+            rotation: (self.transform.rotation + steps)%n,
+        };
+        res
     }
     
-    //  returns set of possible shape transformed variants
+    //  returns set of possible shape transformed variants, 
+    //  as far as the transformations allowed for the shape go
     pub fn variants(&self, mirrored: bool, rotated: bool) -> Vec<Shape> {
+        let mirrored = mirrored && self.transforms == Transforms::Free;
+        let rotated = rotated && self.transforms != Transforms::Fixed;
         let mut res = vec![];
         {
             let mut add_shape = |shape| {
                 if !res.contains(&shape) { res.push(shape); }
             };
-            let s = self.rotated(Rotation::None);
+            let n = self.grid.rotations();
+            let s = self.turned(0);
             if rotated {
-                add_shape(s.rotated(Rotation::CW90));
-                add_shape(s.rotated(Rotation::CW180));
-                add_shape(s.rotated(Rotation::CW270));
+                for k in 1..n { add_shape(s.turned(k)); }
             }
             add_shape(s);
             
             if mirrored {
                 let m = self.mirrored();
                 if rotated {
-                    add_shape(m.rotated(Rotation::CW90));
-                    add_shape(m.rotated(Rotation::CW180));
-                    add_shape(m.rotated(Rotation::CW270));
+                    for k in 1..n { add_shape(m.turned(k)); }
                 }
                 add_shape(m);
             }
         }
+        for shape in &mut res {
+            shape.transforms = self.transforms;
+            shape.name = self.name.clone();
+            shape.color = self.color.clone();
+        }
+        res
+    }
+
+    //  returns the shape moved to the top left corner, keeping the rest of its properties
+    fn normalized(&self) -> Shape {
+        let mut res = Shape::with_grid(Shape::to_corner(self.squares.clone()), self.grid);
+        res.transform = self.transform;
+        res.transforms = self.transforms;
+        res.name = self.name.clone();
+        res.color = self.color.clone();
         res
     }
 
+    //  returns the canonical form of the shape: of the variants it's allowed to take 
+    //  (moved to the top left corner), the one with the smallest list of squares,
+    //  so that the shapes which can be turned into each other have the same one
+    pub fn canonical(&self) -> Shape {
+        self.normalized().variants(true, true).into_iter()
+            .min_by(|a, b| a.squares.cmp(&b.squares)).unwrap()
+    }
+
+    //  returns the group of the transformations that leave the shape as it is
+    //  (whether or not the shape is allowed to take them)
+    pub fn symmetry_group(&self) -> SymmetryGroup {
+        let shape = self.normalized();
+        let mirrored = shape.mirrored();
+        let n = self.grid.rotations();
+        let rotations = (0..n).filter(|&k| shape.turned(k) == shape).count();
+        let reflections = (0..n).any(|k| mirrored.turned(k) == shape);
+        match (rotations, reflections) {
+            (1, false) => SymmetryGroup::C1,
+            (2, false) => SymmetryGroup::C2,
+            (3, false) => SymmetryGroup::C3,
+            (4, false) => SymmetryGroup::C4,
+            (_, false) => SymmetryGroup::C6,
+            (1, true) => SymmetryGroup::D1,
+            (2, true) => SymmetryGroup::D2,
+            (3, true) => SymmetryGroup::D3,
+            (4, true) => SymmetryGroup::D4,
+            (_, true) => SymmetryGroup::D6,
+        }
+    }
+
+    //  the squares of every row as bits (empty if the shape is too wide)
+    pub fn bit_rows(&self) -> &[u64] {
+        &self.bits
+    }
+
+    //  returns Some(true) if the shape, shifted by (dx, dy) relative to the other one, 
+    //  has a common square with it (or with the squares bordering it, when asked),
+    //  None if the shapes are too wide to have the bit masks
+    pub fn hits(&self, other: &Shape, dx: i32, dy: i32, border: bool) -> Option<bool> {
+        if self.bits.is_empty() || other.bits.is_empty() { return None; }
+        let (rows, dx, dy) = if border { (&other.border_bits, dx + 1, dy + 1) } 
+                             else { (&other.bits, dx, dy) };
+        Some(self.bits.iter().enumerate().any(|(y, &row)| {
+            let y = y as i32 + dy;
+            if y < 0 || y >= rows.len() as i32 { return false; }
+            let row = if dx >= 0 { row.checked_shl(dx as u32) } 
+                      else { row.checked_shr(-dx as u32) };
+            row.unwrap_or(0) & rows[y as usize] != 0
+        }))
+    }
+
+    //  returns the minimum manhattan distance between the squares of the shape, 
+    //  shifted by (dx, dy) relative to the other one, and the other shape's squares,
+    //  None if the shapes are too wide to have the bit masks
+    pub fn bits_distance(&self, other: &Shape, dx: i32, dy: i32) -> Option<i32> {
+        if self.bits.is_empty() || other.bits.is_empty() { return None; }
+        let mut res = i32::MAX;
+        for (y1, &a) in self.bits.iter().enumerate() {
+            if a == 0 { continue; }
+            let (amin, amax) = (a.trailing_zeros() as i32 + dx, 63 - a.leading_zeros() as i32 + dx);
+            for (y2, &b) in other.bits.iter().enumerate() {
+                let vert = (y1 as i32 + dy - y2 as i32).abs();
+                if b == 0 || vert >= res { continue; }
+                let (bmin, bmax) = (b.trailing_zeros() as i32, 63 - b.leading_zeros() as i32);
+                let horz = if amax < bmin { bmin - amax } 
+                    else if bmax < amin { amin - bmax } 
+                    else {
+                        //  the rows interleave, grow one of them until they meet
+                        let base = cmp::min(amin, bmin);
+                        if cmp::max(amax, bmax) - base >= 64 { return None; }
+                        let (a, b) = ((a >> (amin - dx)) << (amin - base), (b >> bmin) << (bmin - base));
+                        (0..64).find(|&k| ((a << k) | (a >> k)) & b != 0).unwrap()
+                    };
+                res = cmp::min(res, vert + horz);
+            }
+        }
+        Some(res)
+    }
+
     //  returns true if square at given coordinate is present
     pub fn is_set(&self, x: i32, y: i32) -> bool {
         x >= 0 && y >= 0 &&
@@ -165,8 +483,8 @@ impl Shape {
     //  with given radius and centered at (0, 0)
     pub fn dist_to_circle(&self, radius: f64, pos: &Vec2i) -> f64 {
         self.squares.iter().map(|p| {
-            let cp = Vec2i {x:pos.x + p.x, y:pos.y + p.y};
-            let dr = cp.len() - radius;
+            let (x, y) = self.grid.point((pos.x + p.x) as f64, (pos.y + p.y) as f64);
+            let dr = (x*x + y*y).sqrt() - radius;
             dr*dr
         }).fold(0.0, |sum, i| sum + i)
     }
@@ -175,8 +493,7 @@ impl Shape {
     pub fn angle_range(&self, pos: &Vec2i) -> (f64, f64) {
         self.squares.iter()
         .fold((f64::INFINITY, f64::NEG_INFINITY), |(amin, amax), p| {
-            let x = (pos.x + p.x) as f64;
-            let y = (pos.y + p.y) as f64;
+            let (x, y) = self.grid.point((pos.x + p.x) as f64, (pos.y + p.y) as f64);
             let mut ang = y.atan2(x);
             if ang < 0.0 { ang += 2.0*PI; }
             (amin.min(ang), amax.max(ang))
@@ -191,7 +508,7 @@ mod tests {
     #[test]
     fn test_shape_parse1() {
         let shape = "*\n*** \n*\n";
-        let shape = Shape::parse(shape);
+        let shape = Shape::parse(shape).unwrap();
         assert_eq!(shape.squares, vec![[0, 0], [0, 1], [0, 2], [1, 1], [2, 1]]);
         assert_eq!((shape.width, shape.height), (3, 3));
 
@@ -211,15 +528,27 @@ mod tests {
     #[test]
     fn test_shape_parse2() {
         let shape = "   *\n****\n";
-        let shape = Shape::parse(shape);
+        let shape = Shape::parse(shape).unwrap();
         assert_eq!(shape.squares, vec![[0, 1], [1, 1], [2, 1], [3, 0], [3, 1]]);
         assert_eq!((shape.width, shape.height), (4, 2));
     }
 
+    #[test]
+    fn test_shape_parse_errors() {
+        match Shape::parse("  \n\n") {
+            Err(PolyfarmError::Parse { shape: 0, line: 1, .. }) => (),
+            _ => panic!("empty shape should not parse")
+        }
+        match Shape::parse("**\n*\t*\n") {
+            Err(PolyfarmError::Parse { shape: 0, line: 2, .. }) => (),
+            _ => panic!("tabs should not parse")
+        }
+    }
+
     #[test]
     fn test_shape_mirrored() {
         let shape = "* **\n   *\n";
-        let shape = Shape::parse(shape);
+        let shape = Shape::parse(shape).unwrap();
         assert_eq!(shape.mirrored().squares,
                    vec![[0, 0], [0, 1], [1, 0], [3, 0]]);
         assert_eq!((shape.width, shape.height), (4, 2));
@@ -228,7 +557,7 @@ mod tests {
     #[test]
     fn test_shape_rotated() {
         let shape = "****\n   *\n";
-        let shape = Shape::parse(shape);
+        let shape = Shape::parse(shape).unwrap();
         assert_eq!(shape.rotated(Rotation::None).squares,
                    vec![[0, 0], [1, 0], [2, 0], [3, 0], [3, 1]]);
         assert_eq!(shape.rotated(Rotation::CW90).squares,
@@ -242,14 +571,117 @@ mod tests {
     #[test]
     fn test_variants1() {
         let shape = "****";
-        let shape = Shape::parse(shape);
+        let shape = Shape::parse(shape).unwrap();
         assert_eq!(2, shape.variants(true, true).len());
     }
     
+    #[test]
+    fn test_variant_transforms() {
+        let shape = Shape::parse("**\n*\n*\n").unwrap();
+        let variants = shape.variants(true, true);
+        assert_eq!(8, variants.len());
+        for v in &variants {
+            //  re-applying the transform to the original yields the variant
+            let mut t = if v.transform.mirrored { shape.mirrored() } else { shape.rotated(Rotation::None) };
+            for _ in 0..v.transform.rotation { t = t.rotated(Rotation::CW90); }
+            assert!(t == *v);
+            assert_eq!(v.transform, t.transform);
+        }
+    }
+
+    #[test]
+    fn test_variants_restricted() {
+        let mut shape = Shape::parse("**\n*\n*\n").unwrap();
+        shape.transforms = Transforms::OneSided;
+        let variants = shape.variants(true, true);
+        assert_eq!(4, variants.len());
+        assert!(variants.iter().all(|v| !v.transform.mirrored && v.transforms == Transforms::OneSided));
+        shape.transforms = Transforms::Fixed;
+        let variants = shape.variants(true, true);
+        assert_eq!(1, variants.len());
+        assert_eq!(Transform::identity(), variants[0].transform);
+        //  the global flags restrict the free shapes further
+        shape.transforms = Transforms::Free;
+        assert_eq!(4, shape.variants(false, true).len());
+        assert!(Transforms::OneSided.allows(Transform { mirrored: false, rotation: 3 }));
+        assert!(!Transforms::OneSided.allows(Transform { mirrored: true, rotation: 0 }));
+        assert!(!Transforms::Fixed.allows(Transform { mirrored: false, rotation: 2 }));
+    }
+
+    #[test]
+    fn test_canonical() {
+        //  the L-tetromino, turned and flipped in every way, indented
+        let shape = Shape::parse("  O\n  O\n  OO\n").unwrap();
+        let canonical = shape.canonical();
+        assert_eq!(Vec2i::new(0, 0), canonical.squares[0]);
+        for v in &shape.variants(true, true) {
+            assert!(v.canonical() == canonical);
+        }
+        //  the one-sided ones tell the mirror images apart, the fixed ones any turns
+        let mut shape = Shape::parse("O\nO\nOO\n").unwrap();
+        let (mut mirrored, mut turned) = (shape.mirrored(), shape.rotated(Rotation::CW90));
+        shape.transforms = Transforms::OneSided;
+        mirrored.transforms = Transforms::OneSided;
+        turned.transforms = Transforms::OneSided;
+        assert!(shape.canonical() != mirrored.canonical());
+        assert!(shape.canonical() == turned.canonical());
+        shape.transforms = Transforms::Fixed;
+        assert!(shape.canonical() == shape);
+
+        //  the variants of all the pentominoes are the 63 fixed ones
+        use std::collections::HashSet;
+        let pentominoes = ["OOOOO", ".OO\nOO\n.O", "OO\n.OOO", "OOOO\nO", "OOO\n.OO", 
+            "OOO\n.O\n.O", "O.O\nOOO", "OOO\nO\nO", "O\nOO\n.OO", ".O\nOOO\n.O", 
+            "OOOO\n.O", "OO\n.O\n.OO"];
+        let fixed: HashSet<Shape> = pentominoes.iter()
+            .flat_map(|s| Shape::parse(&s.replace('.', " ")).unwrap().variants(true, true)).collect();
+        assert_eq!(63, fixed.len());
+        let free: HashSet<Shape> = fixed.iter().map(|s| s.canonical()).collect();
+        assert_eq!(12, free.len());
+    }
+
+    #[test]
+    fn test_symmetry_group() {
+        let group = |s: &str| Shape::parse(&s.replace('.', " ")).unwrap().symmetry_group();
+        assert_eq!(SymmetryGroup::D2, group("OOOOO"));
+        assert_eq!(SymmetryGroup::D4, group(".O\nOOO\n.O"));
+        assert_eq!(SymmetryGroup::D4, group("OO\nOO"));
+        assert_eq!(SymmetryGroup::D1, group("OOO\n.O\n.O"));
+        assert_eq!(SymmetryGroup::D1, group("O\nOO\n.OO"));
+        assert_eq!(SymmetryGroup::C2, group("OO\n.O\n.OO"));
+        assert_eq!(SymmetryGroup::C1, group(".OO\nOO\n.O"));
+        assert_eq!(SymmetryGroup::C4, group(".O\n.OOO\nOOO\n..O"));
+        assert_eq!(SymmetryGroup::D2, group("  OOO\n  OOO"));
+        assert_eq!(8, SymmetryGroup::D4.order());
+        assert_eq!("D2", SymmetryGroup::D2.name());
+    }
+
+    #[test]
+    fn test_hex_variants() {
+        let hex = |s: &str| Shape::parse_hex(&s.replace('.', " ")).unwrap();
+        let cases = [("O", SymmetryGroup::D6), ("O O O", SymmetryGroup::D2),
+            ("O O\n.O", SymmetryGroup::D3), ("O O\n.O.O", SymmetryGroup::D2),
+            ("O . O\n.O.O", SymmetryGroup::D1),
+            ("O\n.O O\n....O", SymmetryGroup::C2), ("O O\n.O\n..O O", SymmetryGroup::C1)];
+        for &(s, group) in &cases {
+            let shape = hex(s);
+            assert_eq!(Grid::Hex, shape.grid);
+            assert_eq!(group, shape.symmetry_group(), "{}", s);
+            assert_eq!(12/group.order(), shape.variants(true, true).len(), "{}", s);
+        }
+        //  six turns make the full circle
+        let shape = hex("O\n.O O\n....O");
+        let mut turned = shape.turned(1);
+        for _ in 1..6 { turned = turned.turned(1); }
+        assert_eq!(shape.squares, turned.squares);
+        assert!(shape.turned(1).squares != shape.squares);
+        assert!(Shape::parse_hex("OO").is_err());
+    }
+
     #[test]
     fn test_variants2() {
         let shape = "*\n*\n***";
-        let shape = Shape::parse(shape);
+        let shape = Shape::parse(shape).unwrap();
         assert_eq!(4, shape.variants(true, true).len());
     }
 }
@@ -0,0 +1,347 @@
+// ------------------------------------------------------------------------------------------------
+// shapefile.rs
+// ------------------------------------------------------------------------------------------------
+//  Shape files come in two formats. The original one is just the shapes separated
+//  by blank lines, any character but space being a square. The versioned one starts
+//  with the "polyfarm-shapes 2" line, and then:
+//
+//      # comments start with '#' at the start of a line or after a space
+//      [L] count=2 transforms=one-sided color=#ff8800
+//      OOO
+//      O..
+//
+//  Every shape may start with a header: the name in brackets (possibly empty), followed
+//  by any of count (copies of the shape in the bundle, 1 by default), transforms
+//  (free, one-sided or fixed, free by default) and color (hex RGB).
+//  The shapes are separated by blank lines or by the headers, '.' is an empty square.
+//
+//  With "polyfarm-shapes 2 grid=hex" the shapes are polyhexes, drawn in staggered rows
+//  (see Shape::parse_hex):
+//
+//      [propeller]
+//       O
+//        O O
+//       O
+use polyomino::shape::{Shape, Transforms};
+use polyomino::grid::Grid;
+use polyomino::error::PolyfarmError;
+
+pub const SHAPE_FILE_HEADER : &str = "polyfarm-shapes";
+pub const SHAPE_FILE_VERSION : u32 = 2;
+
+//  Shape, as given in a shape file
+pub struct ShapeDef {
+    pub shape: Shape,               //  along with the name, the color and the transforms
+    pub count: u32,                 //  copies of the shape in the bundle
+}
+
+impl ShapeDef {
+    pub fn new(shape: Shape) -> ShapeDef {
+        ShapeDef { shape, count: 1 }
+    }
+}
+
+//  parses the shapes of a file in either format (Windows line endings are fine too)
+pub fn parse_shape_file(input: &str) -> Result<Vec<ShapeDef>, PolyfarmError> {
+    let lines: Vec<&str> = input.lines().map(|line| line.trim_end_matches('\r')).collect();
+    let first = lines.iter()
+        .position(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'));
+    match first {
+        Some(i) if lines[i].trim_start().starts_with(SHAPE_FILE_HEADER) => parse_versioned(&lines, i),
+        _ => parse_plain(&lines)
+    }
+}
+
+//  parses the blank line separated shapes of the original format
+fn parse_plain(lines: &[&str]) -> Result<Vec<ShapeDef>, PolyfarmError> {
+    //  split into blocks of non-blank lines, remembering where each one starts
+    let mut blocks: Vec<(usize, String)> = vec![];
+    let mut in_block = false;
+    for (i, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() {
+            in_block = false;
+            continue;
+        }
+        if !in_block {
+            blocks.push((i + 1, String::new()));
+            in_block = true;
+        }
+        let block = &mut blocks.last_mut().unwrap().1;
+        block.push_str(line);
+        block.push('\n');
+    }
+    blocks.iter().enumerate().map(|(k, &(first_line, ref block))| {
+        Shape::parse(block).map(ShapeDef::new).map_err(|e| e.at(k, first_line))
+    }).collect()
+}
+
+//  writes the shapes in the versioned format, with a header before the ones that have
+//  a name or any attributes other than the defaults
+pub fn format_shape_file(defs: &[ShapeDef]) -> String {
+    let grid = defs.first().map(|def| def.shape.grid).unwrap_or_default();
+    let mut res = match grid {
+        Grid::Square => format!("{} {}\n", SHAPE_FILE_HEADER, SHAPE_FILE_VERSION),
+        _ => format!("{} {} grid={}\n", SHAPE_FILE_HEADER, SHAPE_FILE_VERSION, grid.name())
+    };
+    for def in defs {
+        let shape = &def.shape;
+        let mut attrs = vec![];
+        if def.count != 1 {
+            attrs.push(format!("count={}", def.count));
+        }
+        if shape.transforms != Transforms::Free {
+            attrs.push(format!("transforms={}", shape.transforms.name()));
+        }
+        if let Some(ref color) = shape.color {
+            attrs.push(format!("color=#{}", color));
+        }
+        res.push('\n');
+        if shape.name.is_some() || !attrs.is_empty() {
+            res += &format!("[{}]", shape.name.as_deref().unwrap_or(""));
+            for attr in &attrs { res += &format!(" {}", attr); }
+            res.push('\n');
+        }
+        for row in format_rows(shape) {
+            res += &row;
+            res.push('\n');
+        }
+    }
+    res
+}
+
+//  draws the rows of the shape: the squares, or the hexagons two columns apart
+//  and shifted by a column every row, with '.' for the empty cells in between
+fn format_rows(shape: &Shape) -> Vec<String> {
+    match shape.grid {
+        Grid::Square => (0..shape.height).map(|y| {
+            let row: String = (0..shape.width)
+                .map(|x| if shape.is_set(x, y) { 'O' } else { '.' }).collect();
+            String::from(row.trim_end_matches('.'))
+        }).collect(),
+        Grid::Hex => {
+            let start = shape.squares.iter().map(|s| 2*s.x + s.y).min().unwrap();
+            (0..shape.height).map(|y| {
+                let (first, last) = (0..shape.width).filter(|&x| shape.is_set(x, y))
+                    .fold((i32::MAX, i32::MIN), |(a, b), x| (a.min(x), b.max(x)));
+                let mut row = " ".repeat((2*first + y - start) as usize);
+                for x in first..(last + 1) {
+                    if x > first { row.push(' '); }
+                    row.push(if shape.is_set(x, y) { 'O' } else { '.' });
+                }
+                row
+            }).collect()
+        }
+    }
+}
+
+//  where the comment starts on the line ('#' at the start or after a space), if anywhere
+fn comment_start(line: &str) -> usize {
+    let mut prev = ' ';
+    for (k, c) in line.char_indices() {
+        if c == '#' && prev.is_whitespace() { return k; }
+        prev = c;
+    }
+    line.len()
+}
+
+//  Shape of the versioned format, as it's being read
+struct Block<'a> {
+    header: Option<(usize, &'a str)>,   //  header line number and text, if any
+    rows: Vec<(usize, String)>,         //  line numbers and squares of the rows
+}
+
+//  parses the versioned format, given the index of the version line
+fn parse_versioned(lines: &[&str], start: usize) -> Result<Vec<ShapeDef>, PolyfarmError> {
+    //  the version and the attributes of the whole file, possibly followed by a comment
+    let line = lines[start].trim();
+    let rest = &line[SHAPE_FILE_HEADER.len()..comment_start(line)];
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return Err(PolyfarmError::parse(0, start + 1,
+            &format!("expected '{} <version>', got '{}'", SHAPE_FILE_HEADER, line)));
+    }
+    let mut items = rest.split_whitespace();
+    let version = items.next().unwrap_or("");
+    if version.parse::<u32>().ok() != Some(SHAPE_FILE_VERSION) {
+        return Err(PolyfarmError::parse(0, start + 1,
+            &format!("unsupported shape file version '{}'", version)));
+    }
+    let mut grid = Grid::Square;
+    for item in items {
+        let err = |message: String| PolyfarmError::Parse { shape: 0, line: start + 1, message };
+        let mut parts = item.splitn(2, '=');
+        match (parts.next().unwrap(), parts.next().unwrap_or("")) {
+            ("grid", value) => grid = Grid::from_name(value).ok_or_else(|| 
+                err(format!("grid must be square or hex, got '{}'", value)))?,
+            (key, _) => return Err(err(format!("unknown shape file attribute '{}'", key)))
+        }
+    }
+    let mut res = vec![];
+    let mut block: Option<Block> = None;
+    for (i, &line) in lines.iter().enumerate().skip(start + 1) {
+        let text = &line[..comment_start(line)];
+        if text.trim().is_empty() {
+            //  blank lines end the shape, the comment lines don't
+            if line.trim().is_empty() {
+                if let Some(b) = block.take() { res.push(finish_block(b, res.len(), grid)?); }
+            }
+            continue;
+        }
+        if text.trim_start().starts_with('[') {
+            if let Some(b) = block.take() { res.push(finish_block(b, res.len(), grid)?); }
+            block = Some(Block { header: Some((i + 1, text)), rows: vec![] });
+        } else {
+            block.get_or_insert(Block { header: None, rows: vec![] })
+                .rows.push((i + 1, text.replace('.', " ")));
+        }
+    }
+    if let Some(b) = block.take() { res.push(finish_block(b, res.len(), grid)?); }
+    Ok(res)
+}
+
+//  builds the shape with the given index from its rows and header
+fn finish_block(block: Block, k: usize, grid: Grid) -> Result<ShapeDef, PolyfarmError> {
+    if block.rows.is_empty() {
+        return Err(PolyfarmError::parse(k, block.header.unwrap().0, "empty shape"));
+    }
+    //  the squares start at the left, however the rows are indented
+    let indent = block.rows.iter()
+        .map(|(_, row)| row.len() - row.trim_start_matches(' ').len()).min().unwrap();
+    let text: String = block.rows.iter().map(|(_, row)| format!("{}\n", &row[indent..]))
+        .collect();
+    let shape = match grid {
+        Grid::Square => Shape::parse(&text),
+        Grid::Hex => Shape::parse_hex(&text)
+    };
+    let shape = shape.map_err(|e| match e {
+        PolyfarmError::Parse { line, message, .. } =>
+            PolyfarmError::Parse { shape: k, line: block.rows[line - 1].0, message },
+        e => e
+    })?;
+    let mut res = ShapeDef::new(shape);
+    if let Some((line, header)) = block.header {
+        apply_header(&mut res, header, k, line)?;
+    }
+    Ok(res)
+}
+
+//  reads the name and the attributes from the "[name] key=value ..." header
+fn apply_header(def: &mut ShapeDef, header: &str, k: usize, line: usize)
+    -> Result<(), PolyfarmError>
+{
+    let err = |message: String| PolyfarmError::Parse { shape: k, line, message };
+    let header = header.trim();
+    let end = header.find(']')
+        .ok_or_else(|| err(String::from("missing ']' after the shape name")))?;
+    let name = header[1..end].trim();
+    if !name.is_empty() {
+        def.shape.name = Some(String::from(name));
+    }
+    for item in header[end + 1..].split_whitespace() {
+        let mut parts = item.splitn(2, '=');
+        let (key, value) = (parts.next().unwrap(), parts.next().unwrap_or(""));
+        match key {
+            "count" => def.count = value.parse().ok().filter(|&n| n > 0).ok_or_else(||
+                err(format!("count must be a positive number, got '{}'", value)))?,
+            "transforms" => def.shape.transforms = Transforms::from_name(value).ok_or_else(||
+                err(format!("transforms must be free, one-sided or fixed, got '{}'", value)))?,
+            "color" => {
+                let hex = value.trim_start_matches('#');
+                if !(hex.len() == 3 || hex.len() == 6) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(err(format!("color must be hex RGB like #ff8800, got '{}'", value)));
+                }
+                def.shape.color = Some(hex.to_lowercase());
+            },
+            _ => return Err(err(format!("unknown shape attribute '{}'", key)))
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_versioned() {
+        let input = "# pieces\r\npolyfarm-shapes 2\r\n\r\n[L] count=2 transforms=one-sided color=#FF8800\r\n\
+            OOO   # the long side\r\nO..\r\n# the square\r\n[ Big square ]\r\n.OO\r\n.OO\r\n\r\n\
+            O\r\nO\r\n";
+        let defs = parse_shape_file(input).unwrap();
+        assert_eq!(3, defs.len());
+        assert_eq!(3, parse_shape_file(&input.replace("shapes 2", "shapes 2  # my pieces")).unwrap().len());
+        assert_eq!(Some("L"), defs[0].shape.name.as_deref());
+        assert_eq!((2, Transforms::OneSided), (defs[0].count, defs[0].shape.transforms));
+        assert_eq!(Some("ff8800"), defs[0].shape.color.as_deref());
+        assert_eq!((3, 2, 4), (defs[0].shape.width, defs[0].shape.height, defs[0].shape.squares.len()));
+        assert_eq!(Some("Big square"), defs[1].shape.name.as_deref());
+        assert_eq!((2, 2), (defs[1].shape.width, defs[1].shape.height));
+        assert_eq!((1, Transforms::Free, None), (defs[2].count, defs[2].shape.transforms, defs[2].shape.name.clone()));
+        assert_eq!((1, 2), (defs[2].shape.width, defs[2].shape.height));
+
+        //  the original format, where '#' is a square
+        let defs = parse_shape_file("##\n#\n\n# #\n###\n").unwrap();
+        assert_eq!(2, defs.len());
+        assert_eq!(5, defs[1].shape.squares.len());
+    }
+
+    #[test]
+    fn test_format_shape_file() {
+        let input = "polyfarm-shapes 2\n[L] count=2 transforms=one-sided color=#FF8800\nOOO\nO..\n\
+            [] transforms=fixed\n.OO\nOO\n\nO\n";
+        let text = format_shape_file(&parse_shape_file(input).unwrap());
+        assert_eq!("polyfarm-shapes 2\n\n[L] count=2 transforms=one-sided color=#ff8800\nOOO\nO\n\n\
+            [] transforms=fixed\n.OO\nOO\n\nO\n", text);
+        let defs = parse_shape_file(&text).unwrap();
+        assert_eq!(3, defs.len());
+        assert_eq!((2, Transforms::OneSided), (defs[0].count, defs[0].shape.transforms));
+        assert_eq!(Transforms::Fixed, defs[1].shape.transforms);
+        assert!(defs[1].shape == Shape::parse(" OO\nOO\n").unwrap());
+    }
+
+    #[test]
+    fn test_parse_hex() {
+        let input = "polyfarm-shapes 2 grid=hex\n[ring]\n O O\nO . O\n O O\n\n[bar] count=2\n  O O O\n";
+        let defs = parse_shape_file(input).unwrap();
+        assert_eq!(2, defs.len());
+        assert_eq!(Grid::Hex, defs[0].shape.grid);
+        let commented = parse_shape_file(&input.replace("grid=hex", "grid=hex # hexagons")).unwrap();
+        assert_eq!(Grid::Hex, commented[1].shape.grid);
+        assert_eq!(6, defs[0].shape.squares.len());
+        assert!(!defs[0].shape.is_set(1, 1));
+        assert_eq!((3, 1), (defs[1].shape.width, defs[1].shape.height));
+        //  written back the same way
+        let text = format_shape_file(&defs);
+        assert_eq!("polyfarm-shapes 2 grid=hex\n\n[ring]\n O O\nO . O\n O O\n\n[bar] count=2\nO O O\n", text);
+
+        let check = |input: &str, line: usize| match parse_shape_file(input) {
+            Err(PolyfarmError::Parse { line: l, .. }) => assert_eq!(line, l),
+            _ => panic!("expected a parse error for {:?}", input)
+        };
+        check("polyfarm-shapes 2 grid=hex\nO O\nO O\n", 3);
+        check("polyfarm-shapes 2 grid=hex\nOO\n", 2);
+        check("polyfarm-shapes 2 grid=tri\nO\n", 1);
+        check("polyfarm-shapes 2 cells=hex\nO\n", 1);
+    }
+
+    #[test]
+    fn test_parse_versioned_errors() {
+        let check = |input: &str, shape: usize, line: usize| {
+            match parse_shape_file(input) {
+                Err(PolyfarmError::Parse { shape: s, line: l, .. }) => assert_eq!((shape, line), (s, l)),
+                _ => panic!("expected a parse error for {:?}", input)
+            }
+        };
+        check("polyfarm-shapes 3\nO\n", 0, 1);
+        check("polyfarm-shapes 2 O\nO\n", 0, 1);
+        check("polyfarm-shapesX 2\nO\n", 0, 1);
+        check("polyfarm-shapes2\nO\n", 0, 1);
+        check("polyfarm-shapes 2\nO\n\n[T] count=0\nOOO\n", 1, 4);
+        check("polyfarm-shapes 2\n[T] transforms=any\nOOO\n", 0, 2);
+        check("polyfarm-shapes 2\n[T color=red\nOOO\n", 0, 2);
+        check("polyfarm-shapes 2\n[T] color=red\nOOO\n", 0, 2);
+        check("polyfarm-shapes 2\n[T] size=2\nOOO\n", 0, 2);
+        check("polyfarm-shapes 2\nO\n[T]\n\nOOO\n", 1, 3);
+        check("polyfarm-shapes 2\n[T]\n# comment\nO\tO\n", 0, 4);
+        assert_eq!(Some(Transforms::Fixed), Transforms::from_name("fixed"));
+    }
+}
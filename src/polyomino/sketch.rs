@@ -0,0 +1,149 @@
+// ------------------------------------------------------------------------------------------------
+// sketch.rs
+// ------------------------------------------------------------------------------------------------
+use polyomino::math::*;
+use polyomino::layout::{Bundle, Layout, Position};
+use polyomino::grid::Grid;
+use polyomino::error::PolyfarmError;
+
+//  characters denoting empty cells in a sketch
+const EMPTY_CELLS : [char; 2] = [' ', '.'];
+//  characters to draw the pieces with, in order
+const PIECE_CHARS : &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+//  Lettered region of a sketch
+struct Piece {
+    letter: char,
+    squares: Vec<Vec2i>,
+}
+
+//  shifts the squares so that the minimum coordinates are (0, 0),
+//  returns the sorted squares and the shift
+fn normalize(squares: &[Vec2i]) -> (Vec<Vec2i>, Vec2i) {
+    let min_x = squares.iter().map(|s| s.x).min().unwrap_or(0);
+    let min_y = squares.iter().map(|s| s.y).min().unwrap_or(0);
+    let offs = Vec2i::new(min_x, min_y);
+    let mut res: Vec<Vec2i> = squares.iter().map(|&s| s - offs).collect();
+    res.sort();
+    (res, offs)
+}
+
+//  collects the squares of every letter, in the order of appearance
+fn collect_pieces(input: &str) -> Result<Vec<Piece>, PolyfarmError> {
+    let mut pieces: Vec<Piece> = vec![];
+    for (j, line) in input.lines().enumerate() {
+        for (i, c) in line.chars().enumerate() {
+            if c == '\t' {
+                return Err(PolyfarmError::InvalidData(format!(
+                    "line {}: tab characters are not allowed, use spaces instead", j + 1)));
+            }
+            if EMPTY_CELLS.contains(&c) { continue; }
+            let sq = Vec2i::new(i as i32, j as i32);
+            match pieces.iter_mut().find(|p| p.letter == c) {
+                Some(p) => p.squares.push(sq),
+                None => pieces.push(Piece { letter: c, squares: vec![sq] })
+            }
+        }
+    }
+    Ok(pieces)
+}
+
+//  parses a hand-drawn layout, where every piece is drawn with a distinct character
+//  (spaces and dots being empty cells), identifying the pieces with the bundle's shapes.
+//  The pieces are chained by the angle around the layout's center, as arrange_circle does.
+//  The sketches are drawn on the square grid only
+pub fn parse_sketch<'a>(input: &str, bundle: &'a Bundle) -> Result<Layout<'a>, PolyfarmError> {
+    if bundle.iter().flat_map(|v| v.iter()).any(|s| s.grid != Grid::Square) {
+        return Err(PolyfarmError::InvalidData(String::from(
+            "sketches can only be drawn with polyominoes")));
+    }
+    let pieces = collect_pieces(input)?;
+
+    //  match every piece against the shape variants
+    let mut used: Vec<Option<char>> = vec![None; bundle.len()];
+    let mut pos = vec![];
+    for piece in &pieces {
+        let (squares, offs) = normalize(&piece.squares);
+        let matches: Vec<(usize, usize, Vec2i)> = bundle.iter().enumerate()
+            .filter_map(|(i, variants)| {
+                variants.iter().enumerate()
+                    .map(|(k, shape)| (k, normalize(&shape.squares)))
+                    .find(|&(_, (ref vsquares, _))| *vsquares == squares)
+                    .map(|(k, (_, voffs))| (i, k, voffs))
+            }).collect();
+        if matches.is_empty() {
+            return Err(PolyfarmError::InvalidData(format!(
+                "piece '{}' doesn't match any allowed shape variant", piece.letter)));
+        }
+        //  identical shapes in the bundle can be used once each
+        let (i, k, voffs) = match matches.iter().find(|&&(i, _, _)| used[i].is_none()) {
+            Some(&m) => m,
+            None => {
+                let i = matches[0].0;
+                return Err(PolyfarmError::InvalidData(format!(
+                    "pieces '{}' and '{}' are both shape #{}",
+                    used[i].unwrap(), piece.letter, i + 1)));
+            }
+        };
+        used[i] = Some(piece.letter);
+        let p = offs - voffs;
+        pos.push(Position { x: p.x, y: p.y, shape: i as u16, var: k as u16 });
+    }
+
+    let mut layout = Layout::from_positions(bundle, pos)?;
+    layout.sort_by_angle();
+    Ok(layout)
+}
+
+//  draws the layout as a sketch, one character per piece, in the chain order
+pub fn draw_sketch(layout: &Layout) -> String {
+    let (lt, rb) = layout.bounds();
+    let (w, h) = ((rb.x - lt.x) as usize, (rb.y - lt.y) as usize);
+    let mut rows = vec![vec!['.'; w]; h];
+    for (i, p) in layout.pos.iter().enumerate() {
+        let c = PIECE_CHARS.chars().nth(i)
+            .unwrap_or_else(|| char::from_u32(0xC0 + i as u32).unwrap());
+        for sq in &layout.shape_by_pos(p).squares {
+            rows[(p.y + sq.y - lt.y) as usize][(p.x + sq.x - lt.x) as usize] = c;
+        }
+    }
+    rows.iter().map(|row| row.iter().collect::<String>() + "\n").collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polyomino::layout::parse_bundle;
+
+    const TETROMINOES: &str = include_str!("../../data/tetromino.txt");
+
+    #[test]
+    fn test_parse_sketch() {
+        let bundle = parse_bundle(TETROMINOES, true, true).unwrap();
+        let sketch =
+            ".BB..\n\
+             ABBCC\n\
+             A...C\n\
+             A...C\n\
+             A...E\n\
+             DDDEE\n\
+             .D.E.\n";
+        let layout = parse_sketch(sketch, &bundle).unwrap();
+        assert_eq!(5, layout.pos.len());
+        assert_eq!(9.0, layout.score());
+        
+        let redrawn = parse_sketch(&draw_sketch(&layout), &bundle).unwrap();
+        assert_eq!(layout.pos, redrawn.pos);
+    }
+
+    #[test]
+    fn test_parse_sketch_errors() {
+        let bundle = parse_bundle(TETROMINOES, true, true).unwrap();
+        //  unknown shape
+        assert!(parse_sketch("AAAAA\nB\nC\nD\nE\n", &bundle).is_err());
+        //  duplicated shape
+        assert!(parse_sketch("AAAA BBBB\n\nC\nD\nE\n", &bundle).is_err());
+        //  wrong number of pieces
+        assert!(parse_sketch("AAAA\n", &bundle).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+// ------------------------------------------------------------------------------------------------
+// strategy.rs
+// ------------------------------------------------------------------------------------------------
+use std::f64::consts::{PI};
+use std::cmp;
+use std::thread;
+use rand::{Rng, SeedableRng, StdRng};
+use serde_json::Value;
+
+use polyomino::layout::{Layout, Bundle};
+use polyomino::farm::{FarmConfig, GenerationStats};
+use polyomino::error::PolyfarmError;
+
+//  What the search strategies get from the driver: the shapes, the parameters
+//  and the means to run on several threads deterministically
+pub struct Context<'a> {
+    pub bundle: &'a Bundle,
+    pub config: FarmConfig,
+    pub radius: f64,            //  radius of the circle to lay out the fresh layouts along
+    pub island: usize,          //  island the search runs on (from 1), 0 for a single population
+}
+
+//  Search algorithm, run by Farm an iteration at a time.
+//  The driver takes care of the output, the checkpoints, the statistics and of when to stop
+pub trait Strategy<'a> {
+    //  creates the starting layouts, from the known ones (if any)
+    fn initialize(&mut self, ctx: &Context<'a>, seeds: &[Layout<'a>]);
+
+    //  runs the given iteration (starting from 1)
+    fn step(&mut self, ctx: &Context<'a>, it: u32);
+
+    //  the layouts the search keeps, with their scores, by descending score
+    fn best(&self) -> Vec<(&Layout<'a>, f64)>;
+
+    //  fills in the strategy's own part of the iteration statistics,
+    //  returning the text to add to the log line
+    fn report(&self, _ctx: &Context<'a>, _it: u32, _stats: &mut GenerationStats) -> String {
+        String::new()
+    }
+
+    //  the layouts to save into the checkpoints, None if the strategy can't be resumed
+    fn population(&self) -> Option<Vec<&Layout<'a>>> {
+        None
+    }
+
+    //  the strategy's own data to save into the checkpoints along with the layouts
+    fn state(&self) -> Option<Value> {
+        None
+    }
+
+    //  continues the search from the saved layouts and data
+    fn restore(&mut self, _ctx: &Context<'a>, _population: Vec<Layout<'a>>, 
+        _state: Option<Value>) -> Result<(), PolyfarmError>
+    {
+        Err(PolyfarmError::Config(String::from("the search strategy can't be resumed")))
+    }
+}
+
+impl<'a> Context<'a> {
+    //  constructor
+    pub fn new(bundle: &'a Bundle, config: &FarmConfig) -> Context<'a> {
+        Context { 
+            bundle, config: config.clone(), radius: Context::estimate_radius(bundle), island: 0 
+        }
+    }
+
+    //  context for one of the islands: its part of the generation 
+    //  and of the threads, and random generators of its own
+    pub fn island(&self, island: usize, gen_size: u32, threads: u32) -> Context<'a> {
+        let config = FarmConfig { gen_size, threads, ..self.config.clone() };
+        Context { bundle: self.bundle, config, radius: self.radius, island }
+    }
+
+    //  finds approximate radius of a circle to lay out the shapes along
+    fn estimate_radius(bundle: &Bundle) -> f64 {
+        let len = bundle.iter().map(|v| v[0].estimate_len())
+            .fold(0.0, |sum, i| sum + i);
+        len/(2.0*PI)
+    }
+
+    //  creates a random generator for the given iteration and stream
+    //  (0 for the main thread, 1.. for the workers), so that the results
+    //  don't depend on how the threads get scheduled
+    pub fn rng(&self, it: u32, stream: usize) -> StdRng {
+        let seed = self.config.seed as usize;
+        let mut seed = vec![seed, seed + 1, seed + 2, seed + 3, it as usize, stream];
+        if self.island > 0 { seed.push(self.island); }
+        SeedableRng::from_seed(&seed[..])
+    }
+
+    //  splits the items into (at most) one contiguous chunk per thread
+    //  and processes them in parallel, passing the chunk's index and offset
+    pub fn run_parallel<T, F>(&self, items: &mut [T], f: F)
+        where T: Send, F: Fn(usize, usize, &mut [T]) + Sync
+    {
+        let threads = self.config.threads as usize;
+        if threads == 1 {
+            f(0, 0, items);
+            return;
+        }
+        let chunk_size = cmp::max(1, items.len().div_ceil(threads));
+        let f = &f;
+        thread::scope(|scope| {
+            for (i, chunk) in items.chunks_mut(chunk_size).enumerate() {
+                scope.spawn(move || f(i, i*chunk_size, chunk));
+            }
+        });
+    }
+
+    //  creates a fresh layout, arranged around the circle
+    pub fn fresh_layout<R: Rng>(&self, rng: &mut R) -> Layout<'a> {
+        let mut layout = Layout::new(self.bundle);
+        layout.shuffle(rng);
+        layout.arrange_circle(self.radius);
+        layout.center();
+        layout
+    }
+}
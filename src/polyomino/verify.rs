@@ -0,0 +1,113 @@
+// ------------------------------------------------------------------------------------------------
+// verify.rs
+// ------------------------------------------------------------------------------------------------
+use polyomino::layout::{Layout, Overlap};
+
+//  Break in the wall between two consecutive shapes of the chain
+#[derive(Debug, PartialEq)]
+pub struct Gap {
+    pub first: usize,       //  position index
+    pub second: usize,      //  the next position index (cyclically)
+    pub distance: i32,      //  number of squares missing in between
+}
+
+//  Outcome of checking a layout with the same rules as the search
+#[derive(Debug)]
+pub struct Report {
+    pub overlaps: Vec<(usize, usize)>,  //  position indices of the overlapping shapes
+    pub gaps: Vec<Gap>,                 //  places where the wall leaks
+    pub area: Option<usize>,            //  enclosed area, if the wall is closed
+}
+
+impl Report {
+    //  a valid farm has no overlapping shapes and a closed wall
+    pub fn is_valid(&self) -> bool {
+        self.overlaps.is_empty() && self.area.is_some()
+    }
+}
+
+//  checks the layout for overlaps and leaks, and measures the enclosed area.
+//  The shapes are expected to be in the order they go along the wall
+pub fn verify(layout: &Layout) -> Report {
+    let pos = &layout.pos;
+    let nshapes = pos.len();
+
+    let mut overlaps = vec![];
+    for i in 0..nshapes {
+        let sh1 = layout.shape_by_pos(&pos[i]);
+        for j in (i + 1)..nshapes {
+            let sh2 = layout.shape_by_pos(&pos[j]);
+            if Layout::overlap_status(sh1, sh2, &pos[i].p(), &pos[j].p()) == Overlap::Overlap {
+                overlaps.push((i, j));
+            }
+        }
+    }
+
+    let area = layout.flood_fill(|_, _| {});
+    let mut gaps = vec![];
+    if area.is_none() && nshapes > 1 {
+        for i in 0..nshapes {
+            let j = (i + 1)%nshapes;
+            let d = Layout::distance(layout.shape_by_pos(&pos[i]), layout.shape_by_pos(&pos[j]),
+                &pos[i].p(), &pos[j].p());
+            if d > 0 {
+                gaps.push(Gap { first: i, second: j, distance: d });
+            }
+        }
+    }
+    Report { overlaps, gaps, area }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polyomino::layout::{parse_bundle, Position};
+
+    const I_TROMINOES: &str = "OOO\n\nOOO\n\nO\nO\nO\n\nO\nO\nO\n";
+
+    #[test]
+    fn test_verify_closed() {
+        let bundle = parse_bundle(I_TROMINOES, false, false).unwrap();
+        let layout = Layout::from_positions(&bundle, vec![
+            Position { x: 0, y: 0, shape: 0, var: 0 },
+            Position { x: 3, y: 0, shape: 2, var: 0 },
+            Position { x: 1, y: 3, shape: 1, var: 0 },
+            Position { x: 0, y: 1, shape: 3, var: 0 },
+        ]).unwrap();
+        let report = verify(&layout);
+        assert!(report.is_valid());
+        assert_eq!(Some(4), report.area);
+        assert!(report.gaps.is_empty());
+    }
+
+    #[test]
+    fn test_verify_leaking() {
+        let bundle = parse_bundle(I_TROMINOES, false, false).unwrap();
+        let layout = Layout::from_positions(&bundle, vec![
+            Position { x: 0, y: 0, shape: 0, var: 0 },
+            Position { x: 4, y: 0, shape: 2, var: 0 },
+            Position { x: 1, y: 3, shape: 1, var: 0 },
+            Position { x: 0, y: 1, shape: 3, var: 0 },
+        ]).unwrap();
+        let report = verify(&layout);
+        assert!(!report.is_valid());
+        assert!(report.overlaps.is_empty());
+        assert_eq!(None, report.area);
+        assert_eq!(vec![Gap { first: 0, second: 1, distance: 1 }, 
+                        Gap { first: 1, second: 2, distance: 1 }], report.gaps);
+    }
+
+    #[test]
+    fn test_verify_overlapping() {
+        let bundle = parse_bundle(I_TROMINOES, false, false).unwrap();
+        let layout = Layout::from_positions(&bundle, vec![
+            Position { x: 0, y: 0, shape: 0, var: 0 },
+            Position { x: 2, y: 0, shape: 2, var: 0 },
+            Position { x: 1, y: 3, shape: 1, var: 0 },
+            Position { x: 0, y: 1, shape: 3, var: 0 },
+        ]).unwrap();
+        let report = verify(&layout);
+        assert!(!report.is_valid());
+        assert_eq!(vec![(0, 1)], report.overlaps);
+    }
+}